@@ -0,0 +1,73 @@
+//! A JSON API over HTTP for drawing and checking lineups without the GUI. The request and
+//! response layouts are documented in `docs/api.md`.
+
+use crate::http::{self, error, json, Reply};
+use crate::roster::Roster;
+use crate::service::{self, ServiceError};
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use tiny_http::{Method, Request, Server};
+
+/// Serves the API until the process ends.
+pub struct ApiServer {
+    server: Server,
+    roster: Roster,
+}
+
+impl ApiServer {
+    /// Listens on `address`, e.g. `127.0.0.1:8080`, or `127.0.0.1:0` for any free port.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let server =
+            Server::http(address).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        Ok(Self {
+            server,
+            roster: Roster::built_in(),
+        })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn run(self) {
+        for mut request in self.server.incoming_requests() {
+            let response = match http::read(&mut request) {
+                Err(reply) => reply,
+                // A request that trips a bug answers with 500 instead of stopping the server.
+                Ok(incoming) => panic::catch_unwind(AssertUnwindSafe(|| {
+                    route(&request, &incoming, &self.roster)
+                }))
+                .unwrap_or_else(|_| error(500, "The request could not be answered.")),
+            };
+            let _ = request.respond(response);
+        }
+    }
+}
+
+fn route(request: &Request, incoming: &http::Incoming, roster: &Roster) -> Reply {
+    let answer = |serve: &dyn Fn(&service::Query) -> Result<Reply, ServiceError>| {
+        match service::parse_query(&incoming.body).and_then(|query| serve(&query)) {
+            Ok(reply) => reply,
+            Err(ServiceError::BadRequest(message)) => error(400, &message),
+            Err(ServiceError::NoLineup(failure)) => json(422, &failure),
+        }
+    };
+
+    match (request.method(), incoming.path.as_str()) {
+        (Method::Get, "/roster") => json(200, roster),
+        (Method::Post, "/draw") => {
+            answer(&|query| service::draw(query, roster).map(|report| json(200, &report)))
+        }
+        (Method::Post, "/validate") => {
+            answer(&|query| service::validate(query, roster).map(|report| json(200, &report)))
+        }
+        (Method::Post, "/feasibility") => answer(&|query| {
+            service::feasibility(query, roster).map(|feasibility| json(200, &feasibility))
+        }),
+        (_, "/roster" | "/draw" | "/validate" | "/feasibility") => {
+            error(405, "Method not allowed.")
+        }
+        _ => error(404, "Not found."),
+    }
+}
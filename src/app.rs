@@ -1,363 +1,223 @@
-use core::cmp::Ordering;
-use rand::seq::SliceRandom;
+use crate::character::{
+    same_characters, Character, Difficulty, PairKind, PairRules, RelationshipKind, Relationships,
+};
+use crate::constraints::average_aggression;
+use crate::counting::{LineupCount, PendingCount};
+use crate::export::{ExportBundle, MergeTarget, EXPORT_VERSION};
+use crate::fairness::{self, Entry, FairDraw, Seal, Verification};
+use crate::history::{self, HistoryEntry, Veto};
+use crate::identity::{Deal, DealMode};
+use crate::matching::TypedLineup;
+use crate::persistence::{self, SCHEMA_VERSION};
+use crate::randomizer::{
+    self, DrawFailure, PendingInclusion, TierEstimate, INCLUSION_DRAWS, MAX_TRIES,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::room::Room;
+use crate::roster::Roster;
+use crate::settings::{save_preset, DrawSettings, Preset, RelationshipRule};
+use crate::widgets::{DrawSettingsEditor, LineupView};
 use rand::thread_rng;
+use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Eq, Ord, PartialOrd)]
-pub enum Difficulty {
-    Easy,
-    Medium,
-    Hard,
-}
-
-#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Eq, Ord, PartialOrd)]
-pub enum AggressionLevel {
-    Peaceful,
-    Random,
-    Aggressive,
-}
-
-#[derive(serde::Deserialize, serde::Serialize, Clone)]
-pub struct Character {
-    pub name: String,
-    pub difficulty: Difficulty,
-    pub aggression: f32,
-    pub is_robot: bool,
-    pub is_officer: bool,
-    pub cares_about_briefcase: bool,
-    pub cares_about_artifact: bool,
-    pub wants_contamintaion: bool,
-}
-
-impl Eq for Character {}
+/// How many candidates the per-difficulty inclusion estimate adds each frame.
+const TIER_SAMPLES_PER_FRAME: usize = 100;
 
-impl Ord for Character {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.name.cmp(&other.name)
-    }
-}
-
-impl PartialOrd for Character {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl PartialEq for Character {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
-    }
+/// How the inclusion chances table is sorted.
+#[derive(PartialEq)]
+enum InclusionOrder {
+    Name,
+    Chance,
 }
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct TemplateApp {
-    number_of_characters: usize,
-    at_least_one_robot: bool,
-    at_least_one_officer: bool,
-    at_least_two_agents_per_goal: bool,
-    preferred_maximum_difficulty: Difficulty,
-    preferred_aggression_level: AggressionLevel,
+    schema_version: u32,
+    settings: DrawSettings,
+    presets: Vec<Preset>,
+    custom_characters: Vec<Character>,
+    /// Edited pair rules of built-in characters, replacing the roster's, by name.
+    pair_rule_overrides: BTreeMap<String, PairRules>,
+    /// Edited relationships of built-in characters, replacing the roster's, by name.
+    relationship_overrides: BTreeMap<String, Relationships>,
+    history: Vec<HistoryEntry>,
     show_detailed_character_info: bool,
+    show_inclusion_chances: bool,
+    player_count: usize,
+    deal_mode: DealMode,
     found_character_list: bool,
     #[serde(skip)]
-    global_easy_character_list: Vec<Character>,
+    new_preset_name: String,
+    #[serde(skip)]
+    renaming_preset: Option<usize>,
+    #[serde(skip)]
+    rename_buffer: String,
+    #[serde(skip)]
+    new_character: Character,
+    #[serde(skip)]
+    new_pair_rule: (String, PairKind, String),
+    #[serde(skip)]
+    new_relationship: (String, RelationshipKind, String),
+    #[serde(skip)]
+    transfer_code: String,
+    #[serde(skip)]
+    transfer_path: String,
+    #[serde(skip)]
+    transfer_messages: Vec<String>,
+    /// Whether an import replaces the draw settings instead of keeping the current ones.
+    #[serde(skip)]
+    import_settings: bool,
+    #[serde(skip)]
+    last_failure: Option<DrawFailure>,
     #[serde(skip)]
-    global_medium_character_list: Vec<Character>,
+    tier_inclusion: Option<TierEstimate>,
+    /// The number of valid lineups, with the settings and pool it is counted for.
     #[serde(skip)]
-    global_hard_character_list: Vec<Character>,
+    lineup_count: Option<(DrawSettings, Vec<Character>, PendingCount)>,
     #[serde(skip)]
+    inclusion: Option<PendingInclusion>,
+    #[serde(skip)]
+    inclusion_order: InclusionOrder,
+    #[serde(skip)]
+    unreadable_state: Option<String>,
+    #[serde(skip)]
+    load_error: Option<String>,
+    #[serde(skip)]
+    roster: Roster,
+    /// The lineup on screen, kept along with its deal so the deal can be checked after a
+    /// restart.
     randomized_character_list: Vec<Character>,
+    /// Lineups offered to choose from, when drawing alternatives.
+    #[serde(skip)]
+    alternative_lineups: Vec<Vec<Character>>,
+    /// The settings the alternatives were drawn with, recorded with the chosen one.
+    #[serde(skip)]
+    alternatives_settings: DrawSettings,
+    /// Vetoes cast against the shown lineup that haven't been replaced yet.
+    #[serde(skip)]
+    pending_vetoes: Vec<Veto>,
+    /// Vetoes already replaced in the shown lineup.
+    #[serde(skip)]
+    applied_vetoes: Vec<Veto>,
+    #[serde(skip)]
+    new_veto: Veto,
+    #[serde(skip)]
+    veto_failure: Option<DrawFailure>,
+    /// The identities dealt from the shown lineup.
+    deal: Option<Deal>,
+    #[serde(skip)]
+    deal_error: Option<String>,
+    /// The seat the device has been passed to during pass-and-play.
+    pass_seat: usize,
+    /// Whether the current seat's identity is on screen.
+    #[serde(skip)]
+    seat_revealed: bool,
+    #[serde(skip)]
+    reveal_identities: bool,
+    /// The commitment to the deal, made once every player has an identity. Saved, so the
+    /// deal can still be opened against it after the game.
+    deal_seal: Option<Seal>,
+    /// A commitment noted down at the start of the game, to check the seal against.
+    #[serde(skip)]
+    noted_commitment: String,
+    /// Phrases committed to for the next fair draw.
+    #[serde(skip)]
+    fair_entries: Vec<Entry>,
+    #[serde(skip)]
+    new_fair_entry: (String, String),
+    #[serde(skip)]
+    fair_draw: Option<FairDraw>,
+    /// Commitments the players noted down themselves, to check the revealed phrases against.
+    #[serde(skip)]
+    fair_noted: Vec<String>,
+    #[serde(skip)]
+    fair_verification: Option<Verification>,
+    /// A lineup pasted in to check against the settings.
+    #[serde(skip)]
+    typed_lineup: String,
+    /// The room players join to receive their identities on their own devices.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    room: Option<Room>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    room_port: u16,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    room_error: Option<String>,
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
         Self {
-            number_of_characters: 15,
-            at_least_one_robot: true,
-            at_least_one_officer: true,
-            at_least_two_agents_per_goal: true,
-            preferred_maximum_difficulty: Difficulty::Hard,
-            preferred_aggression_level: AggressionLevel::Random,
+            schema_version: SCHEMA_VERSION,
+            settings: DrawSettings::default(),
+            presets: vec![],
+            custom_characters: vec![],
+            pair_rule_overrides: BTreeMap::new(),
+            relationship_overrides: BTreeMap::new(),
+            history: vec![],
             show_detailed_character_info: false,
+            show_inclusion_chances: false,
+            player_count: 4,
+            deal_mode: DealMode::Random,
             found_character_list: true,
-            global_easy_character_list: vec![
-                Character {
-                    name: "Astrochimp".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: true,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Counselor".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: true,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Cyborg".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 1.0,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Daredevil".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: true,
-                    wants_contamintaion: true,
-                },
-                Character {
-                    name: "Engineer".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: true,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Exile".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Inspector".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.5,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Maintenance Clones".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.5,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Medical".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.0,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Security".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 1.0,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: true,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Station Chief".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Stowaway".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Troubleshooter".to_string(),
-                    difficulty: Difficulty::Easy,
-                    aggression: 0.5,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-            ],
-            global_medium_character_list: vec![
-                Character {
-                    name: "Microbiologist".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: true,
-                },
-                Character {
-                    name: "Colonel".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.5,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: true,
-                },
-                Character {
-                    name: "Operative".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Stranger".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Boarder".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Corpsicle".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.5,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Doctor".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: true,
-                    wants_contamintaion: true,
-                },
-                Character {
-                    name: "Drones".to_string(),
-                    difficulty: Difficulty::Medium,
-                    aggression: 0.5,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-            ],
-            global_hard_character_list: vec![
-                Character {
-                    name: "Legal".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 0.0,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Billionaire".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Botanist".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: true,
-                },
-                Character {
-                    name: "Consort".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 0.0,
-                    is_robot: true,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Digital Assistant".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 0.0,
-                    is_robot: false,
-                    is_officer: true,
-                    cares_about_briefcase: false,
-                    cares_about_artifact: false,
-                    wants_contamintaion: false,
-                },
-                Character {
-                    name: "Telepathic Rat".to_string(),
-                    difficulty: Difficulty::Hard,
-                    aggression: 1.0,
-                    is_robot: false,
-                    is_officer: false,
-                    cares_about_briefcase: true,
-                    cares_about_artifact: true,
-                    wants_contamintaion: false,
-                },
-            ],
+            new_preset_name: String::new(),
+            renaming_preset: None,
+            rename_buffer: String::new(),
+            new_character: Character {
+                name: String::new(),
+                difficulty: Difficulty::Easy,
+                aggression: 0.0,
+                tags: BTreeSet::new(),
+                pair_rules: PairRules::default(),
+                relationships: Relationships::default(),
+            },
+            new_pair_rule: (String::new(), PairKind::Requires, String::new()),
+            new_relationship: (String::new(), RelationshipKind::Targets, String::new()),
+            transfer_code: String::new(),
+            transfer_path: "stationfall_randomizer.json".to_string(),
+            transfer_messages: vec![],
+            import_settings: false,
+            last_failure: None,
+            tier_inclusion: None,
+            lineup_count: None,
+            inclusion: None,
+            inclusion_order: InclusionOrder::Chance,
+            unreadable_state: None,
+            load_error: None,
+            roster: Roster::built_in(),
             randomized_character_list: vec![],
+            alternative_lineups: vec![],
+            alternatives_settings: DrawSettings::default(),
+            pending_vetoes: vec![],
+            applied_vetoes: vec![],
+            new_veto: Veto {
+                player: String::new(),
+                character: String::new(),
+            },
+            veto_failure: None,
+            deal: None,
+            deal_error: None,
+            pass_seat: 0,
+            seat_revealed: false,
+            reveal_identities: false,
+            deal_seal: None,
+            noted_commitment: String::new(),
+            fair_entries: vec![],
+            new_fair_entry: (String::new(), String::new()),
+            fair_draw: None,
+            fair_noted: vec![],
+            fair_verification: None,
+            typed_lineup: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            room: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            room_port: 7878,
+            #[cfg(not(target_arch = "wasm32"))]
+            room_error: None,
         }
     }
 }
@@ -370,11 +230,34 @@ impl TemplateApp {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        // State set aside in an earlier session is kept until it is restored or discarded.
+        let backup = cc
+            .storage
+            .and_then(|storage| storage.get_string(persistence::BACKUP_KEY))
+            .filter(|blob| !blob.is_empty());
+        if let Some(blob) = cc
+            .storage
+            .and_then(|storage| storage.get_string(eframe::APP_KEY))
+        {
+            return match persistence::load::<Self>(&blob) {
+                Ok(app) => Self {
+                    schema_version: SCHEMA_VERSION,
+                    unreadable_state: backup,
+                    ..app
+                },
+                // Keep the unreadable state around so that saving doesn't destroy it.
+                Err(error) => Self {
+                    unreadable_state: Some(blob),
+                    load_error: Some(error.to_string()),
+                    ..Default::default()
+                },
+            };
         }
 
-        Default::default()
+        Self {
+            unreadable_state: backup,
+            ..Default::default()
+        }
     }
 }
 
@@ -382,223 +265,824 @@ impl eframe::App for TemplateApp {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self);
+        // Storage can't remove keys, so a discarded or restored backup is left empty.
+        storage.set_string(
+            persistence::BACKUP_KEY,
+            self.unreadable_state.clone().unwrap_or_default(),
+        );
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let Self {
-            number_of_characters,
-            at_least_one_robot,
-            at_least_one_officer,
-            at_least_two_agents_per_goal,
-            preferred_maximum_difficulty,
-            preferred_aggression_level,
+            schema_version: _,
+            settings,
+            presets,
+            custom_characters,
+            pair_rule_overrides,
+            relationship_overrides,
+            history,
             show_detailed_character_info,
+            show_inclusion_chances,
+            player_count,
+            deal_mode,
             found_character_list,
-            global_easy_character_list,
-            global_medium_character_list,
-            global_hard_character_list,
+            new_preset_name,
+            renaming_preset,
+            rename_buffer,
+            new_character,
+            new_pair_rule,
+            new_relationship,
+            transfer_code,
+            transfer_path,
+            transfer_messages,
+            import_settings,
+            last_failure,
+            tier_inclusion,
+            lineup_count,
+            inclusion,
+            inclusion_order,
+            unreadable_state,
+            load_error,
+            roster,
             randomized_character_list,
+            alternative_lineups,
+            alternatives_settings,
+            pending_vetoes,
+            applied_vetoes,
+            new_veto,
+            veto_failure,
+            deal,
+            deal_error,
+            pass_seat,
+            seat_revealed,
+            reveal_identities,
+            deal_seal,
+            noted_commitment,
+            fair_entries,
+            new_fair_entry,
+            fair_draw,
+            fair_noted,
+            fair_verification,
+            typed_lineup,
+            #[cfg(not(target_arch = "wasm32"))]
+            room,
+            #[cfg(not(target_arch = "wasm32"))]
+            room_port,
+            #[cfg(not(target_arch = "wasm32"))]
+            room_error,
         } = self;
+        let mut restored = None;
+        let mut discard_backup = false;
+
+        // Players choose their identities in the room, so the deal follows along. A room
+        // hosting a deal that has since been replaced or cleared is closed.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let shared = room.as_ref().map(Room::deal);
+            match (shared, deal.as_mut()) {
+                (Some(shared), Some(deal)) if shared.deals_same_options(deal) => {
+                    *deal = shared;
+                    ctx.request_repaint_after(std::time::Duration::from_millis(500));
+                }
+                (None, _) => {}
+                _ => *room = None,
+            }
+        }
+
+        let pool: Vec<Character> = roster
+            .characters
+            .iter()
+            .map(|character| {
+                let mut character = character.clone();
+                if let Some(pair_rules) = pair_rule_overrides.get(&character.name) {
+                    character.pair_rules = pair_rules.clone();
+                }
+                if let Some(relationships) = relationship_overrides.get(&character.name) {
+                    character.relationships = relationships.clone();
+                }
+                character
+            })
+            .chain(custom_characters.iter().cloned())
+            .collect();
 
         egui::SidePanel::left("side_panel").show(ctx, |ui| {
             ui.heading("Config");
 
+            ui.add(
+                DrawSettingsEditor::new("draw_settings", settings, roster).tier_chances(
+                    |settings| {
+                        if !tier_inclusion
+                            .as_ref()
+                            .map_or(false, |estimate| estimate.is_for(settings, &pool))
+                        {
+                            *tier_inclusion = Some(TierEstimate::new(settings, &pool));
+                        }
+                        let estimate = tier_inclusion.as_mut().unwrap();
+                        if !estimate.is_done() {
+                            estimate.sample(TIER_SAMPLES_PER_FRAME, &mut thread_rng());
+                            ctx.request_repaint();
+                        }
+                        estimate.chances()
+                    },
+                ),
+            );
+
             ui.horizontal(|ui| {
-                ui.label("Number of Characters");
-                egui::ComboBox::from_id_source(1)
-                    .selected_text(format!("{:?}", number_of_characters))
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(number_of_characters, 12, "12 (2p, 3p, 4p)");
-                        ui.selectable_value(number_of_characters, 14, "14 (5p)");
-                        ui.selectable_value(number_of_characters, 15, "15 (6p)");
-                        ui.selectable_value(number_of_characters, 17, "17 (7p)");
-                        ui.selectable_value(number_of_characters, 19, "19 (8p)");
-                        ui.selectable_value(number_of_characters, 20, "20 (9p)");
-                    });
+                ui.label("Show Detailed Character Info");
+                ui.add(egui::Checkbox::without_text(show_detailed_character_info));
             });
 
             ui.horizontal(|ui| {
-                ui.label("Preferred Maximum Difficulty");
-                egui::ComboBox::from_id_source(2)
-                    .selected_text(match preferred_maximum_difficulty {
-                        Difficulty::Easy => "Easy",
-                        Difficulty::Medium => "Medium",
-                        Difficulty::Hard => "Hard",
-                    })
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(preferred_maximum_difficulty, Difficulty::Easy, "Easy");
-                        ui.selectable_value(
-                            preferred_maximum_difficulty,
-                            Difficulty::Medium,
-                            "Medium",
-                        );
-                        ui.selectable_value(preferred_maximum_difficulty, Difficulty::Hard, "Hard");
-                    });
+                ui.label("Show Inclusion Chances");
+                ui.add(egui::Checkbox::without_text(show_inclusion_chances));
             });
 
-            ui.horizontal(|ui| {
-                ui.label("Preferred Aggression Level");
-                egui::ComboBox::from_id_source(3)
-                    .selected_text(match preferred_aggression_level {
-                        AggressionLevel::Aggressive => "Aggressive",
-                        AggressionLevel::Random => "Random",
-                        AggressionLevel::Peaceful => "Peaceful",
-                    })
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            preferred_aggression_level,
-                            AggressionLevel::Aggressive,
-                            "Aggressive",
-                        );
-                        ui.selectable_value(
-                            preferred_aggression_level,
-                            AggressionLevel::Random,
-                            "Random",
-                        );
-                        ui.selectable_value(
-                            preferred_aggression_level,
-                            AggressionLevel::Peaceful,
-                            "Peaceful",
-                        );
+            let stale = !matches!(
+                lineup_count,
+                Some((for_settings, for_pool, _))
+                    if for_settings == settings && same_characters(for_pool, &pool)
+            );
+            if stale {
+                let count = PendingCount::start(pool.clone(), roster.clone(), settings.clone());
+                *lineup_count = Some((settings.clone(), pool.clone(), count));
+            }
+            let count = lineup_count.as_mut().unwrap().2.result();
+            // Drawing stays possible unless the count found no lineups at all.
+            let possible = count.map_or(true, |count| count.lineups != Some(0));
+
+            match count {
+                None => {
+                    ui.label("Counting valid lineups…");
+                    ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                }
+                Some(LineupCount {
+                    lineups: Some(0),
+                    blocking,
+                }) => {
+                    ui.colored_label(ui.visuals().warn_fg_color, "No valid lineups");
+                    let shortages = randomizer::shortages(&pool, settings);
+                    ui.label(if !shortages.is_empty() {
+                        shortages
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    } else if !blocking.is_empty() {
+                        format!(
+                            "Loosen one of these rules to get lineups: {}.",
+                            blocking.join(", ")
+                        )
+                    } else {
+                        "Several rules rule out every lineup together; loosen more than one."
+                            .to_string()
                     });
-            });
+                }
+                Some(LineupCount {
+                    lineups: Some(lineups),
+                    ..
+                }) => {
+                    ui.label(format!("{} valid lineups", group_digits(*lineups)));
+                }
+                Some(LineupCount { lineups: None, .. }) => {
+                    ui.label("Too many pair rules to count the valid lineups.");
+                }
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("At Least One Officer");
-                ui.add(egui::Checkbox::without_text(at_least_one_officer));
-            });
+            if ui
+                .add_enabled(possible, egui::Button::new("Pick Characters"))
+                .clicked()
+            {
+                alternative_lineups.clear();
+                pending_vetoes.clear();
+                applied_vetoes.clear();
+                *veto_failure = None;
+                *deal = None;
+                *deal_seal = None;
 
-            ui.horizontal(|ui| {
-                ui.label("At Least One Robot");
-                ui.add(egui::Checkbox::without_text(at_least_one_robot));
-            });
+                if settings.alternatives > 1 {
+                    match randomizer::draw_alternatives(&pool, roster, settings, &mut thread_rng())
+                    {
+                        Err(failure) => {
+                            randomized_character_list.clear();
+                            *found_character_list = false;
+                            *last_failure = Some(failure);
+                        }
+                        Ok(lineups) => {
+                            randomized_character_list.clear();
+                            *alternative_lineups = lineups;
+                            *alternatives_settings = settings.clone();
+                            *found_character_list = true;
+                            *last_failure = None;
+                        }
+                    }
+                } else {
+                    match randomizer::draw(&pool, roster, settings, &mut thread_rng()) {
+                        Err(failure) => {
+                            randomized_character_list.clear();
+                            *found_character_list = false;
+                            *last_failure = Some(failure);
+                        }
+                        Ok(lineup) => {
+                            *randomized_character_list = lineup;
+                            *found_character_list = true;
+                            *last_failure = None;
 
-            ui.horizontal(|ui| {
-                ui.label("At Least Two Characters Per Goal ");
-                ui.add(egui::Checkbox::without_text(at_least_two_agents_per_goal));
-            });
+                            history::record(
+                                history,
+                                HistoryEntry::new(randomized_character_list, settings),
+                            );
+                        }
+                    }
+                }
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("Show Detailed Character Info");
-                ui.add(egui::Checkbox::without_text(show_detailed_character_info));
-            });
+            ui.collapsing("Fair Draw", |ui| {
+                ui.label(
+                    "Every player commits to a secret phrase; the phrases together seed the \
+                     lineup and the identity deal.",
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Player");
+                    ui.text_edit_singleline(&mut new_fair_entry.0);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Phrase");
+                    ui.add(egui::TextEdit::singleline(&mut new_fair_entry.1).password(true));
+                });
+                let player = new_fair_entry.0.trim();
+                let valid = !player.is_empty()
+                    && !new_fair_entry.1.is_empty()
+                    && !fair_entries.iter().any(|entry| entry.player == player);
+                if ui.add_enabled(valid, egui::Button::new("Commit")).clicked() {
+                    fair_entries.push(Entry::new(player, &new_fair_entry.1, &mut thread_rng()));
+                    new_fair_entry.0.clear();
+                    new_fair_entry.1.clear();
+                }
+
+                let mut entry_to_remove = None;
+                for (index, entry) in fair_entries.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{}: {}", entry.player, entry.commitment));
+                        if ui.small_button("Remove").clicked() {
+                            entry_to_remove = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = entry_to_remove {
+                    fair_entries.remove(index);
+                }
 
-            if ui.button("Pick Characters").clicked() {
-                let mut finished = false;
-                let mut num_tries = 0;
+                if ui
+                    .add_enabled(
+                        !fair_entries.is_empty() && possible,
+                        egui::Button::new("Reveal and Draw"),
+                    )
+                    .clicked()
+                {
+                    // The entries stay committed if the draw fails, so nobody has to commit
+                    // again after the settings are fixed.
+                    match FairDraw::new(fair_entries.clone(), &pool, roster, settings, *deal_mode) {
+                        Err(failure) => {
+                            randomized_character_list.clear();
+                            *found_character_list = false;
+                            *last_failure = Some(failure);
+                        }
+                        Ok(draw) => {
+                            fair_entries.clear();
+                            *randomized_character_list = draw.lineup.clone();
+                            *found_character_list = true;
+                            *last_failure = None;
+                            alternative_lineups.clear();
+                            pending_vetoes.clear();
+                            applied_vetoes.clear();
+                            *veto_failure = None;
+                            match &draw.deal {
+                                Ok(fair_deal) => {
+                                    *deal = Some(fair_deal.clone());
+                                    *deal_error = None;
+                                }
+                                Err(error) => {
+                                    *deal = None;
+                                    *deal_error = Some(error.to_string());
+                                }
+                            }
+                            *pass_seat = 0;
+                            *seat_revealed = false;
+                            *reveal_identities = false;
+                            *deal_seal = None;
+
+                            history::record(
+                                history,
+                                HistoryEntry::new(randomized_character_list, settings),
+                            );
+                            *fair_draw = Some(draw);
+                            *fair_verification = None;
+                            fair_noted.clear();
+                        }
+                    }
+                }
 
-                while !finished {
-                    randomized_character_list.truncate(0);
+                let Some(draw) = fair_draw else {
+                    return;
+                };
+                ui.separator();
+                ui.label("Revealed phrases:");
+                fair_noted.resize(draw.entries.len(), String::new());
+                for (entry, noted) in draw.entries.iter().zip(fair_noted.iter_mut()) {
+                    ui.label(format!("{}: \"{}\"", entry.player, entry.phrase));
+                    ui.weak(format!("Nonce: {}", entry.nonce));
+                    ui.horizontal(|ui| {
+                        ui.label("Noted commitment");
+                        ui.text_edit_singleline(noted);
+                    });
+                }
+                ui.weak(
+                    "Each commitment is the SHA-256 of the nonce, a newline and the phrase. \
+                     Paste the commitments you noted down to check them; the draw is only \
+                     confirmed fair once every one is checked.",
+                );
+                ui.monospace(format!("Seed: {}", fairness::to_hex(&draw.seed())));
 
-                    num_tries += 1;
-                    if num_tries > 1000 {
-                        break;
+                if ui.button("Verify").clicked() {
+                    *fair_verification = Some(draw.verify(roster, fair_noted));
+                }
+                if let Some(verification) = fair_verification {
+                    for (entry, matches) in draw.entries.iter().zip(&verification.commitments) {
+                        ui.label(match matches {
+                            Some(true) => {
+                                format!("✔ {}'s phrase matches their commitment", entry.player)
+                            }
+                            Some(false) => {
+                                format!("✘ {}'s phrase differs from their commitment", entry.player)
+                            }
+                            None => format!("– {}'s commitment was not checked", entry.player),
+                        });
                     }
+                    ui.label(format!(
+                        "{} The lineup draws again the same",
+                        if verification.lineup { "✔" } else { "✘" }
+                    ));
+                    ui.label(format!(
+                        "{} The identities deal again the same",
+                        if verification.deal { "✔" } else { "✘" }
+                    ));
+                    // Everything that was checked matched, but some commitments weren't.
+                    let incomplete = verification.lineup
+                        && verification.deal
+                        && !verification.commitments.contains(&Some(false));
+                    ui.strong(if verification.passed() {
+                        "The draw was fair."
+                    } else if incomplete {
+                        "Paste every noted commitment to verify the draw."
+                    } else {
+                        "The draw could not be verified."
+                    });
+                }
+            });
+
+            ui.separator();
 
-                    let mut easy_characters: Vec<Character> = global_easy_character_list.clone();
+            ui.collapsing("Presets", |ui| {
+                let mut preset_to_apply = None;
+                let mut preset_to_delete = None;
+                let mut preset_to_rename = None;
 
-                    let mut medium_characters: Vec<Character> =
-                        global_medium_character_list.clone();
+                for (index, preset) in presets.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if *renaming_preset == Some(index) {
+                            ui.text_edit_singleline(rename_buffer);
 
-                    let mut hard_characters: Vec<Character> = global_hard_character_list.clone();
+                            let new_name = rename_buffer.trim();
+                            let name_taken = presets
+                                .iter()
+                                .enumerate()
+                                .any(|(other, preset)| other != index && preset.name == new_name);
 
-                    match preferred_maximum_difficulty {
-                        Difficulty::Easy => {
-                            easy_characters.shuffle(&mut thread_rng());
-                            medium_characters.shuffle(&mut thread_rng());
-                            hard_characters.shuffle(&mut thread_rng());
+                            if ui
+                                .add_enabled(
+                                    !new_name.is_empty() && !name_taken,
+                                    egui::Button::new("Save"),
+                                )
+                                .clicked()
+                            {
+                                preset_to_rename = Some((index, new_name.to_string()));
+                            }
+                            if ui.button("Cancel").clicked() {
+                                *renaming_preset = None;
+                            }
+                        } else {
+                            if preset.settings == *settings {
+                                ui.label("✔");
+                            }
+                            ui.label(preset.name.as_str());
 
-                            randomized_character_list.append(&mut easy_characters);
-                            randomized_character_list.append(&mut medium_characters);
-                            randomized_character_list.append(&mut hard_characters);
+                            if ui.button("Apply").clicked() {
+                                preset_to_apply = Some(index);
+                            }
+                            if ui.button("Rename").clicked() {
+                                *renaming_preset = Some(index);
+                                *rename_buffer = preset.name.clone();
+                            }
+                            if ui.button("Delete").clicked() {
+                                preset_to_delete = Some(index);
+                            }
                         }
-                        Difficulty::Medium => {
-                            randomized_character_list.append(&mut easy_characters);
-                            randomized_character_list.append(&mut medium_characters);
+                    });
+                }
 
-                            randomized_character_list.shuffle(&mut thread_rng());
+                if let Some((index, new_name)) = preset_to_rename {
+                    presets[index].name = new_name;
+                    *renaming_preset = None;
+                }
 
-                            hard_characters.shuffle(&mut thread_rng());
+                if let Some(index) = preset_to_apply {
+                    *settings = presets[index].settings.clone();
+                }
 
-                            randomized_character_list.append(&mut hard_characters);
-                        }
-                        Difficulty::Hard => {
-                            randomized_character_list.append(&mut easy_characters);
-                            randomized_character_list.append(&mut medium_characters);
-                            randomized_character_list.append(&mut hard_characters);
+                if let Some(index) = preset_to_delete {
+                    presets.remove(index);
+                    *renaming_preset = None;
+                }
 
-                            randomized_character_list.shuffle(&mut thread_rng());
-                        }
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(new_preset_name);
+                    let name = new_preset_name.trim();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("Save Current Settings"))
+                        .clicked()
+                    {
+                        save_preset(presets, name, settings);
+                        new_preset_name.clear();
                     }
-                    randomized_character_list.truncate(*number_of_characters);
-
-                    let mut num_officers = 0;
-                    let mut num_robots = 0;
-                    let mut num_artifacts = 0;
-                    let mut num_briefcases = 0;
-                    let mut num_contaminants = 0;
-                    let mut aggresssion = 0.0;
-
-                    for character in &mut *randomized_character_list {
-                        if character.is_officer {
-                            num_officers += 1;
-                        }
+                });
+            });
 
-                        if character.is_robot {
-                            num_robots += 1;
-                        }
+            ui.collapsing("Custom Characters", |ui| {
+                let mut character_to_delete = None;
 
-                        if character.cares_about_artifact {
-                            num_artifacts += 1;
+                for (index, character) in custom_characters.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(character.name.as_str());
+                        if ui.button("Delete").clicked() {
+                            character_to_delete = Some(index);
                         }
+                    });
+                }
 
-                        if character.cares_about_briefcase {
-                            num_briefcases += 1;
-                        }
+                if let Some(index) = character_to_delete {
+                    custom_characters.remove(index);
+                }
+
+                ui.separator();
+
+                egui::Grid::new("new_character").show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut new_character.name);
+                    ui.end_row();
+
+                    ui.label("Difficulty");
+                    egui::ComboBox::from_id_source(4)
+                        .selected_text(match new_character.difficulty {
+                            Difficulty::Easy => "Easy",
+                            Difficulty::Medium => "Medium",
+                            Difficulty::Hard => "Hard",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut new_character.difficulty,
+                                Difficulty::Easy,
+                                "Easy",
+                            );
+                            ui.selectable_value(
+                                &mut new_character.difficulty,
+                                Difficulty::Medium,
+                                "Medium",
+                            );
+                            ui.selectable_value(
+                                &mut new_character.difficulty,
+                                Difficulty::Hard,
+                                "Hard",
+                            );
+                        });
+                    ui.end_row();
+
+                    ui.label("Aggression");
+                    ui.add(
+                        egui::Slider::new(&mut new_character.aggression, 0.0..=1.0).step_by(0.5),
+                    );
+                    ui.end_row();
 
-                        if character.wants_contamintaion {
-                            num_contaminants += 1;
+                    for tag in &roster.tags {
+                        let mut has_tag = new_character.has_tag(&tag.id);
+                        ui.label(format!("{} {}", tag.icon, tag.label));
+                        if ui.add(egui::Checkbox::without_text(&mut has_tag)).changed() {
+                            if has_tag {
+                                new_character.tags.insert(tag.id.clone());
+                            } else {
+                                new_character.tags.remove(&tag.id);
+                            }
                         }
+                        ui.end_row();
+                    }
+                });
+
+                let name = new_character.name.trim().to_string();
+                let name_taken = roster
+                    .characters
+                    .iter()
+                    .chain(custom_characters.iter())
+                    .any(|character| character.name == name);
+
+                if name_taken {
+                    ui.label("A character with this name already exists.");
+                }
+
+                if ui
+                    .add_enabled(
+                        !name.is_empty() && !name_taken,
+                        egui::Button::new("Add Character"),
+                    )
+                    .clicked()
+                {
+                    let mut character = new_character.clone();
+                    character.name = name;
+                    custom_characters.push(character);
+                    new_character.name.clear();
+                }
+            });
+
+            ui.collapsing("Pair Rules", |ui| {
+                let mut rule_to_add = None;
+                let mut rule_to_remove = None;
 
-                        aggresssion += character.aggression;
+                for character in &pool {
+                    for (kind, name) in character.pair_rules.iter() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} {} {}", character.name, kind.name(), name));
+                            if ui.button("Delete").clicked() {
+                                rule_to_remove = Some((character.name.clone(), kind, name.clone()));
+                            }
+                        });
                     }
+                }
+                if pool.iter().all(|character| character.pair_rules.is_empty()) {
+                    ui.label("No pair rules yet.");
+                }
+
+                ui.separator();
+
+                let (subject, kind, other) = new_pair_rule;
+                ui.horizontal(|ui| {
+                    character_combo(ui, "pair_rule_subject", subject, &pool);
+                    egui::ComboBox::from_id_source("pair_rule_kind")
+                        .selected_text(kind.name())
+                        .show_ui(ui, |ui| {
+                            for option in PairKind::ALL {
+                                ui.selectable_value(kind, option, option.name());
+                            }
+                        });
+                    character_combo(ui, "pair_rule_other", other, &pool);
+                });
+                if ui
+                    .add_enabled(
+                        !subject.is_empty() && !other.is_empty() && subject != other,
+                        egui::Button::new("Add Rule"),
+                    )
+                    .clicked()
+                {
+                    rule_to_add = Some((subject.clone(), *kind, other.clone()));
+                }
 
-                    finished = true;
+                for (add, rule) in [(true, rule_to_add), (false, rule_to_remove)] {
+                    let Some((subject, kind, other)) = rule else {
+                        continue;
+                    };
+                    edit_links(
+                        &subject,
+                        custom_characters,
+                        &roster.characters,
+                        pair_rule_overrides,
+                        |character| &mut character.pair_rules,
+                        |pair_rules| {
+                            let names = pair_rules.names_mut(kind);
+                            if add {
+                                names.insert(other);
+                            } else {
+                                names.remove(&other);
+                            }
+                        },
+                    );
+                }
+            });
 
-                    if num_officers == 0 && *at_least_one_officer {
-                        finished = false;
+            ui.collapsing("Relationships", |ui| {
+                for rule in RelationshipRule::ALL {
+                    let mut enabled = settings.relationship_rules.contains(&rule);
+                    if ui.checkbox(&mut enabled, rule.name()).changed() {
+                        if enabled {
+                            settings.relationship_rules.insert(rule);
+                        } else {
+                            settings.relationship_rules.remove(&rule);
+                        }
                     }
+                }
 
-                    if num_robots == 0 && *at_least_one_robot {
-                        finished = false;
+                ui.separator();
+
+                let mut relationship_to_add = None;
+                let mut relationship_to_remove = None;
+
+                for character in &pool {
+                    for (kind, name) in character.relationships.iter() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} {} {}", character.name, kind.name(), name));
+                            if ui.button("Delete").clicked() {
+                                relationship_to_remove =
+                                    Some((character.name.clone(), kind, name.clone()));
+                            }
+                        });
                     }
+                }
+                if pool
+                    .iter()
+                    .all(|character| character.relationships.is_empty())
+                {
+                    ui.label("No relationships yet.");
+                }
 
-                    if *at_least_two_agents_per_goal
-                        && (num_artifacts == 1 || num_briefcases == 1 || num_contaminants == 1)
-                    {
-                        finished = false;
+                ui.separator();
+
+                let (subject, kind, other) = new_relationship;
+                ui.horizontal(|ui| {
+                    character_combo(ui, "relationship_subject", subject, &pool);
+                    egui::ComboBox::from_id_source("relationship_kind")
+                        .selected_text(kind.name())
+                        .show_ui(ui, |ui| {
+                            for option in RelationshipKind::ALL {
+                                ui.selectable_value(kind, option, option.name());
+                            }
+                        });
+                    character_combo(ui, "relationship_other", other, &pool);
+                });
+                if ui
+                    .add_enabled(
+                        !subject.is_empty() && !other.is_empty() && subject != other,
+                        egui::Button::new("Add Relationship"),
+                    )
+                    .clicked()
+                {
+                    relationship_to_add = Some((subject.clone(), *kind, other.clone()));
+                }
+
+                for (add, relationship) in
+                    [(true, relationship_to_add), (false, relationship_to_remove)]
+                {
+                    let Some((subject, kind, other)) = relationship else {
+                        continue;
+                    };
+                    edit_links(
+                        &subject,
+                        custom_characters,
+                        &roster.characters,
+                        relationship_overrides,
+                        |character| &mut character.relationships,
+                        |relationships| {
+                            let names = relationships.names_mut(kind);
+                            if add {
+                                names.insert(other);
+                            } else {
+                                names.remove(&other);
+                            }
+                        },
+                    );
+                }
+            });
+
+            ui.collapsing("History", |ui| {
+                if history.is_empty() {
+                    ui.label("No lineups drawn yet.");
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for entry in history.iter().rev() {
+                            ui.label(entry.characters.join(", "));
+                            for veto in &entry.vetoes {
+                                ui.weak(format!("{} vetoed {}", veto.player, veto.character));
+                            }
+                            ui.separator();
+                        }
+                    });
+
+                if !history.is_empty() && ui.button("Clear History").clicked() {
+                    history.clear();
+                }
+            });
+
+            ui.collapsing("Import / Export", |ui| {
+                let bundle = || ExportBundle {
+                    version: EXPORT_VERSION,
+                    settings: settings.clone(),
+                    presets: presets.clone(),
+                    custom_characters: custom_characters.clone(),
+                    pair_rule_overrides: pair_rule_overrides.clone(),
+                    relationship_overrides: relationship_overrides.clone(),
+                    history: history.clone(),
+                };
+
+                ui.label("Code");
+                ui.add(egui::TextEdit::multiline(transfer_code).desired_rows(3));
+
+                ui.checkbox(import_settings, "Importing replaces my draw settings");
+
+                let mut imported = None;
+
+                ui.horizontal(|ui| {
+                    if ui.button("Export Code").clicked() {
+                        *transfer_code = bundle().to_code();
+                        ui.output_mut(|output| output.copied_text = transfer_code.clone());
+                        *transfer_messages = vec!["Code copied to clipboard.".to_string()];
+                    }
+                    if ui.button("Import Code").clicked() {
+                        imported = Some(ExportBundle::from_code(transfer_code));
                     }
+                });
 
-                    aggresssion /= *number_of_characters as f32;
+                // The browser build has no file system access, so it only offers codes.
+                #[cfg(target_arch = "wasm32")]
+                let _ = transfer_path;
 
-                    if (*preferred_aggression_level == AggressionLevel::Aggressive
-                        && aggresssion < 0.43)
-                        || (*preferred_aggression_level == AggressionLevel::Peaceful
-                            && aggresssion > 0.43)
-                    {
-                        finished = false;
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.label("File");
+                    ui.text_edit_singleline(transfer_path);
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Export File").clicked() {
+                            *transfer_messages =
+                                vec![match std::fs::write(&*transfer_path, bundle().to_json()) {
+                                    Ok(()) => format!("Saved to {}.", transfer_path),
+                                    Err(error) => {
+                                        format!("Could not write {}: {}", transfer_path, error)
+                                    }
+                                }];
+                        }
+                        if ui.button("Import File").clicked() {
+                            match std::fs::read_to_string(&*transfer_path) {
+                                Ok(json) => imported = Some(ExportBundle::from_json(&json)),
+                                Err(error) => {
+                                    *transfer_messages = vec![format!(
+                                        "Could not read {}: {}",
+                                        transfer_path, error
+                                    )];
+                                }
+                            }
+                        }
+                    });
+                }
+
+                match imported {
+                    Some(Ok(bundle)) => {
+                        let report = bundle.merge_into(
+                            MergeTarget {
+                                settings,
+                                presets,
+                                custom_characters,
+                                pair_rule_overrides,
+                                relationship_overrides,
+                                history,
+                            },
+                            &roster.characters,
+                            *import_settings,
+                        );
+
+                        *transfer_messages = vec![format!(
+                            "Imported {}{} presets, {} characters, {} pair rule edits, \
+                             {} relationship edits and {} history entries.",
+                            if report.settings_replaced {
+                                "settings, "
+                            } else {
+                                ""
+                            },
+                            report.presets_added,
+                            report.characters_added,
+                            report.pair_rules_added,
+                            report.relationships_added,
+                            report.history_added
+                        )];
+                        transfer_messages.extend(report.conflicts);
                     }
+                    Some(Err(error)) => *transfer_messages = vec![error.to_string()],
+                    None => {}
                 }
 
-                if num_tries > 1000 {
-                    *found_character_list = false;
-                } else {
-                    randomized_character_list.sort();
-                    *found_character_list = true;
+                for message in transfer_messages.iter() {
+                    ui.label(message.as_str());
                 }
-            }
+            });
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                 ui.horizontal(|ui| {
@@ -615,65 +1099,561 @@ impl eframe::App for TemplateApp {
             });
         });
 
+        if *show_inclusion_chances {
+            egui::SidePanel::right("inclusion_panel").show(ctx, |ui| {
+                ui.heading("Inclusion Chances");
+
+                if !inclusion
+                    .as_ref()
+                    .map_or(false, |estimate| estimate.is_for(settings, &pool))
+                {
+                    *inclusion = Some(PendingInclusion::start(settings, &pool, roster));
+                }
+                let estimate = inclusion.as_mut().unwrap().estimate();
+                if !estimate.is_done() {
+                    ctx.request_repaint();
+                }
+
+                ui.label(format!(
+                    "Based on {} of {} draws with the current settings.",
+                    estimate.draws, INCLUSION_DRAWS
+                ));
+                if estimate.failures > 0 {
+                    ui.label(format!("{} draws found no lineup.", estimate.failures));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Sort by");
+                    ui.selectable_value(inclusion_order, InclusionOrder::Chance, "Chance");
+                    ui.selectable_value(inclusion_order, InclusionOrder::Name, "Name");
+                });
+                ui.separator();
+
+                let mut rows: Vec<(&Character, f32)> = pool
+                    .iter()
+                    .map(|character| {
+                        let chance = estimate.chance(&character.name).unwrap_or(0.0);
+                        (character, chance)
+                    })
+                    .collect();
+                match inclusion_order {
+                    InclusionOrder::Name => rows.sort_by(|a, b| a.0.cmp(b.0)),
+                    InclusionOrder::Chance => rows.sort_by(|a, b| b.1.total_cmp(&a.1)),
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("inclusion_chances").show(ui, |ui| {
+                        for (character, chance) in rows {
+                            ui.label(character.name.as_str());
+                            ui.add(
+                                egui::ProgressBar::new(chance)
+                                    .desired_width(120.0)
+                                    .text(format!("{:.0}%", chance * 100.0)),
+                            );
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
 
-            ui.heading("Randomized Characters");
-            if *found_character_list && !randomized_character_list.is_empty() {
-                if !*show_detailed_character_info {
-                    for character in &mut *randomized_character_list {
-                        ui.label(character.name.as_str());
+            if let Some(blob) = unreadable_state {
+                if let Some(error) = load_error {
+                    ui.colored_label(ui.visuals().warn_fg_color, error.as_str());
+                }
+                ui.label("Your previous state was set aside and is kept as a backup.");
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Restore")
+                        .on_hover_text("Load the backup in place of the current state")
+                        .clicked()
+                    {
+                        match persistence::load::<TemplateApp>(blob) {
+                            Ok(app) => restored = Some(app),
+                            Err(error) => *load_error = Some(error.to_string()),
+                        }
                     }
-                } else {
-                    egui::Grid::new("answer").striped(true).show(ui, |ui| {
-                        ui.label("");
-                        ui.label("Difficulty");
-                        ui.label("Officer");
-                        ui.label("Robot");
-                        ui.label("Artifact");
-                        ui.label("Briefcase");
-                        ui.label("Contamination");
-                        ui.label("Aggression");
-                        ui.end_row();
+                    if ui.button("Discard").clicked() {
+                        discard_backup = true;
+                    }
+                });
+                ui.separator();
+            }
 
-                        for character in &mut *randomized_character_list {
+            ui.heading("Randomized Characters");
+            if !alternative_lineups.is_empty() {
+                let mut chosen = None;
+                ui.columns(alternative_lineups.len(), |columns| {
+                    for (index, (ui, lineup)) in
+                        columns.iter_mut().zip(&*alternative_lineups).enumerate()
+                    {
+                        ui.strong(format!("Option {}", index + 1));
+                        for character in lineup {
                             ui.label(character.name.as_str());
-                            ui.label(match character.difficulty {
-                                Difficulty::Easy => "Easy",
-                                Difficulty::Medium => "Medium",
-                                Difficulty::Hard => "Hard",
-                            });
-                            ui.label(match character.is_officer {
-                                true => "💳",
-                                false => "",
-                            });
-                            ui.label(match character.is_robot {
-                                true => "⚙",
-                                false => "",
-                            });
-                            ui.label(match character.cares_about_artifact {
-                                true => "💎",
-                                false => "",
-                            });
-                            ui.label(match character.cares_about_briefcase {
-                                true => "💼",
-                                false => "",
-                            });
-                            ui.label(match character.wants_contamintaion {
-                                true => "☣",
-                                false => "",
+                        }
+                        ui.separator();
+
+                        ui.label(format!("Aggression {:.2}", average_aggression(lineup)));
+                        let tag_counts = roster.tag_counts(lineup);
+                        let goal_counts: Vec<String> = roster
+                            .goals()
+                            .map(|goal| format!("{} {}", goal.icon, tag_counts[&goal.id]))
+                            .collect();
+                        ui.label(goal_counts.join("  "));
+                        let difficulty_counts: Vec<String> = Difficulty::ALL
+                            .into_iter()
+                            .map(|difficulty| {
+                                let count = lineup
+                                    .iter()
+                                    .filter(|character| character.difficulty == difficulty)
+                                    .count();
+                                format!("{} {}", count, difficulty.name())
+                            })
+                            .collect();
+                        ui.label(difficulty_counts.join(", "));
+
+                        if ui.button("Choose").clicked() {
+                            chosen = Some(index);
+                        }
+                    }
+                });
+
+                if let Some(index) = chosen {
+                    *randomized_character_list = alternative_lineups.swap_remove(index);
+                    alternative_lineups.clear();
+                    pending_vetoes.clear();
+                    applied_vetoes.clear();
+                    *veto_failure = None;
+                    *deal = None;
+                    *deal_seal = None;
+                    history::record(
+                        history,
+                        HistoryEntry::new(randomized_character_list, alternatives_settings),
+                    );
+                }
+            } else if *found_character_list && !randomized_character_list.is_empty() {
+                ui.add(
+                    LineupView::new("drawn_lineup", randomized_character_list, roster, settings)
+                        .pool(&pool)
+                        .detailed(*show_detailed_character_info),
+                );
+
+                ui.separator();
+                ui.collapsing("Veto Round", |ui| {
+                    ui.label(
+                        "Each player may veto one character; all vetoes are replaced at once.",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Player");
+                        ui.text_edit_singleline(&mut new_veto.player);
+                        character_combo(
+                            ui,
+                            "veto_character",
+                            &mut new_veto.character,
+                            randomized_character_list,
+                        );
+
+                        let player = new_veto.player.trim();
+                        let valid = !player.is_empty()
+                            && randomized_character_list
+                                .iter()
+                                .any(|character| character.name == new_veto.character);
+                        if ui.add_enabled(valid, egui::Button::new("Veto")).clicked() {
+                            let veto = Veto {
+                                player: player.to_string(),
+                                character: new_veto.character.clone(),
+                            };
+                            // A player who vetoes again changes their mind.
+                            pending_vetoes.retain(|existing| existing.player != veto.player);
+                            pending_vetoes.push(veto);
+                            new_veto.player.clear();
+                            new_veto.character.clear();
+                        }
+                    });
+
+                    let mut veto_to_remove = None;
+                    for (index, veto) in pending_vetoes.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} vetoes {}", veto.player, veto.character));
+                            if ui.small_button("Remove").clicked() {
+                                veto_to_remove = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = veto_to_remove {
+                        pending_vetoes.remove(index);
+                    }
+                    for veto in &*applied_vetoes {
+                        ui.weak(format!("{} vetoed {}", veto.player, veto.character));
+                    }
+
+                    if ui
+                        .add_enabled(
+                            !pending_vetoes.is_empty(),
+                            egui::Button::new("Replace Vetoed Characters"),
+                        )
+                        .clicked()
+                    {
+                        let round: Vec<Veto> = applied_vetoes
+                            .iter()
+                            .chain(&*pending_vetoes)
+                            .cloned()
+                            .collect();
+                        match randomizer::replace_vetoed(
+                            randomized_character_list,
+                            &round,
+                            &pool,
+                            roster,
+                            settings,
+                            &mut thread_rng(),
+                        ) {
+                            Err(failure) => *veto_failure = Some(failure),
+                            Ok(lineup) => {
+                                let old_names: Vec<String> = randomized_character_list
+                                    .iter()
+                                    .map(|character| character.name.clone())
+                                    .collect();
+                                *randomized_character_list = lineup;
+                                applied_vetoes.append(pending_vetoes);
+                                *veto_failure = None;
+                                *deal = None;
+                                *deal_seal = None;
+
+                                let mut entry =
+                                    HistoryEntry::new(randomized_character_list, settings);
+                                entry.vetoes = applied_vetoes.clone();
+                                match history.last_mut() {
+                                    Some(last) if last.characters == old_names => *last = entry,
+                                    _ => history::record(history, entry),
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(failure) = veto_failure {
+                        ui.label("Could not replace the vetoed characters.");
+                        for shortage in &failure.shortages {
+                            ui.label(format!("✘ {}", shortage));
+                        }
+                        for (rule, rejections) in &failure.rejections {
+                            ui.label(format!("✘ {} rejected {}", rule, rejections));
+                        }
+                    }
+                });
+
+                ui.collapsing("Identities", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Players");
+                        ui.add(egui::DragValue::new(player_count).clamp_range(1..=9));
+                        egui::ComboBox::from_id_source("deal_mode")
+                            .selected_text(deal_mode.name())
+                            .show_ui(ui, |ui| {
+                                for mode in DealMode::ALL {
+                                    ui.selectable_value(deal_mode, mode, mode.name());
+                                }
                             });
-                            ui.label(character.aggression.to_string());
-                            ui.end_row();
+                        if ui.button("Deal Identities").clicked() {
+                            #[cfg(not(target_arch = "wasm32"))]
+                            {
+                                *room = None;
+                            }
+                            match Deal::new(
+                                *deal_mode,
+                                randomized_character_list,
+                                *player_count,
+                                &mut thread_rng(),
+                            ) {
+                                Ok(new_deal) => {
+                                    *deal = Some(new_deal);
+                                    *deal_error = None;
+                                }
+                                Err(error) => {
+                                    *deal = None;
+                                    *deal_error = Some(error.to_string());
+                                }
+                            }
+                            *pass_seat = 0;
+                            *seat_revealed = false;
+                            *reveal_identities = false;
+                            *deal_seal = None;
                         }
                     });
-                }
+                    if let Some(error) = deal_error {
+                        ui.label(format!("✘ {}", error));
+                    }
+
+                    let Some(deal) = deal else {
+                        return;
+                    };
+                    ui.separator();
+
+                    if deal.is_complete() && deal_seal.is_none() {
+                        *deal_seal = Some(Seal::new(&deal.assignment(), &mut thread_rng()));
+                    }
+                    if let Some(seal) = deal_seal {
+                        ui.label("Note down this commitment to check the deal after the game:");
+                        ui.monospace(seal.commitment.as_str());
+                    }
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let hosting = host_room(ui, room, room_port, room_error, deal);
+                    #[cfg(target_arch = "wasm32")]
+                    let hosting = false;
+
+                    if hosting {
+                        if !deal.is_complete() {
+                            return;
+                        }
+                    } else if let Some(seat) = deal.seats.get(*pass_seat) {
+                        let player = seat.player.clone();
+                        if !*seat_revealed {
+                            ui.label(format!("Pass the device to {}.", player));
+                            if ui.button(format!("I am {}", player)).clicked() {
+                                *seat_revealed = true;
+                            }
+                        } else if let Some(identity) = &seat.identity {
+                            ui.label(format!("{}, you are {}.", player, identity));
+                            if ui.button("Hide and Pass On").clicked() {
+                                *pass_seat += 1;
+                                *seat_revealed = false;
+                            }
+                        } else {
+                            ui.label(format!("{}, choose your identity:", player));
+                            let mut chosen = None;
+                            for option in &seat.options {
+                                if ui.button(option.as_str()).clicked() {
+                                    chosen = Some(option.clone());
+                                }
+                            }
+                            if let Some(name) = chosen {
+                                deal.choose(*pass_seat, &name);
+                            }
+                        }
+                        return;
+                    }
+
+                    ui.label("Every player has an identity.");
+                    let unclaimed: Vec<&str> =
+                        deal.unclaimed().into_iter().map(String::as_str).collect();
+                    ui.label(format!("Unclaimed: {}", unclaimed.join(", ")));
+                    ui.checkbox(reveal_identities, "Reveal All Identities");
+                    if *reveal_identities {
+                        for seat in &deal.seats {
+                            if let Some(identity) = &seat.identity {
+                                ui.label(format!("{}: {}", seat.player, identity));
+                            }
+                        }
+
+                        if let Some(seal) = deal_seal {
+                            ui.separator();
+                            ui.monospace(format!("Salt: {}", seal.salt));
+                            ui.weak(
+                                "The commitment is the SHA-256 of the salt, a newline and the \
+                                 lines above.",
+                            );
+                            ui.label(format!(
+                                "{} The identities match the commitment",
+                                if seal.opens(&deal.assignment()) {
+                                    "✔"
+                                } else {
+                                    "✘"
+                                }
+                            ));
+                            ui.horizontal(|ui| {
+                                ui.label("Noted commitment");
+                                ui.text_edit_singleline(noted_commitment);
+                            });
+                            let noted = noted_commitment.trim();
+                            if !noted.is_empty() {
+                                ui.label(if noted.eq_ignore_ascii_case(&seal.commitment) {
+                                    "✔ The noted commitment is the one shown at the start"
+                                } else {
+                                    "✘ The noted commitment differs from the one shown at the \
+                                     start"
+                                });
+                            }
+                        }
+                    }
+                });
             } else if !*found_character_list {
                 ui.label("Could not find character list with given parameters.");
-                ui.label("Adjust maximum difficulty or aggression.");
+                ui.label(
+                    "Adjust maximum difficulty, difficulty quotas, aggression or trait counts.",
+                );
+
+                if let Some(failure) = last_failure {
+                    ui.separator();
+                    if failure.shortages.is_empty() {
+                        ui.label(format!("Out of {} attempts:", MAX_TRIES));
+                        for (rule, rejections) in &failure.rejections {
+                            ui.label(format!("✘ {} rejected {}", rule, rejections));
+                        }
+                    }
+                    for shortage in &failure.shortages {
+                        ui.label(format!("✘ {}", shortage));
+                    }
+                }
             }
 
+            ui.separator();
+            ui.collapsing("Check a Lineup", |ui| {
+                ui.label("Paste a lineup, one name per line or separated by commas.");
+                ui.text_edit_multiline(typed_lineup);
+
+                let typed = TypedLineup::read(typed_lineup, &pool);
+                for note in typed.notes() {
+                    ui.label(note);
+                }
+                let lineup = typed.lineup();
+                if !lineup.is_empty() {
+                    if lineup.len() != settings.number_of_characters {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!(
+                                "The settings call for {} characters, not {}.",
+                                settings.number_of_characters,
+                                lineup.len()
+                            ),
+                        );
+                    }
+                    ui.add(
+                        LineupView::new("typed_lineup", &lineup, roster, settings)
+                            .pool(&pool)
+                            .detailed(*show_detailed_character_info),
+                    );
+                }
+            });
+
             egui::warn_if_debug_build(ui);
         });
+
+        if discard_backup {
+            *unreadable_state = None;
+            *load_error = None;
+        }
+        if let Some(app) = restored {
+            *self = Self {
+                schema_version: SCHEMA_VERSION,
+                ..app
+            };
+        }
+    }
+}
+
+/// Lets players join a room to receive their identities on their own devices instead of
+/// passing this one around. Returns whether a room is open.
+#[cfg(not(target_arch = "wasm32"))]
+fn host_room(
+    ui: &mut egui::Ui,
+    room: &mut Option<Room>,
+    port: &mut u16,
+    error: &mut Option<String>,
+    deal: &Deal,
+) -> bool {
+    let Some(open_room) = room else {
+        ui.horizontal(|ui| {
+            ui.label("Port");
+            ui.add(egui::DragValue::new(port).clamp_range(1024..=65535));
+            if ui.button("Host Room").clicked() {
+                match Room::open(&format!("0.0.0.0:{}", port), deal.clone()) {
+                    Ok(opened) => {
+                        *room = Some(opened);
+                        *error = None;
+                    }
+                    Err(opening_error) => {
+                        *error = Some(format!("Could not open the room: {}", opening_error))
+                    }
+                }
+            }
+        });
+        if let Some(error) = error {
+            ui.label(format!("✘ {}", error));
+        }
+        ui.separator();
+        return room.is_some();
+    };
+
+    ui.strong(format!("Room code: {}", open_room.code()));
+    match open_room.lan_url() {
+        Some(url) => ui.label(format!("Players open {} and enter the code.", url)),
+        None => ui.label(format!(
+            "Players open port {} of this machine and enter the code.",
+            open_room.address().port()
+        )),
+    };
+    for seat in open_room.status() {
+        ui.label(format!(
+            "{} {}: {}",
+            if seat.chosen { "✔" } else { "…" },
+            seat.player,
+            match (seat.joined, seat.chosen) {
+                (false, _) => "waiting to join",
+                (true, false) => "choosing",
+                (true, true) => "has an identity",
+            }
+        ));
+    }
+    if ui.button("Close Room").clicked() {
+        *room = None;
+    }
+    ui.separator();
+    true
+}
+
+/// Formats `n` with thousands separators, e.g. "1,234,567".
+fn group_digits(n: u128) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// A combo box for picking one of the characters in `pool` by name.
+fn character_combo(ui: &mut egui::Ui, id: &str, selected: &mut String, pool: &[Character]) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected.as_str())
+        .show_ui(ui, |ui| {
+            for character in pool {
+                ui.selectable_value(selected, character.name.clone(), character.name.as_str());
+            }
+        });
+}
+
+/// Applies `edit` to one part of a character's links, `part`. Custom characters are changed
+/// in place; built-in characters get an entry in `overrides` for as long as they differ
+/// from the roster.
+fn edit_links<T: Clone + PartialEq>(
+    name: &str,
+    custom_characters: &mut [Character],
+    built_in_characters: &[Character],
+    overrides: &mut BTreeMap<String, T>,
+    part: impl Fn(&mut Character) -> &mut T,
+    edit: impl FnOnce(&mut T),
+) {
+    if let Some(character) = custom_characters
+        .iter_mut()
+        .find(|character| character.name == name)
+    {
+        edit(part(character));
+    } else if let Some(character) = built_in_characters
+        .iter()
+        .find(|character| character.name == name)
+    {
+        let original = part(&mut character.clone()).clone();
+        let mut edited = overrides.get(name).unwrap_or(&original).clone();
+        edit(&mut edited);
+
+        if edited == original {
+            overrides.remove(name);
+        } else {
+            overrides.insert(name.to_string(), edited);
+        }
     }
 }
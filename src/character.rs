@@ -0,0 +1,217 @@
+use core::cmp::Ordering;
+use std::collections::BTreeSet;
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy, Eq, Ord, PartialOrd)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+}
+
+/// Named shortcuts for common [`AggressionRange`](crate::settings::AggressionRange)s.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy, Eq, Ord, PartialOrd)]
+pub enum AggressionLevel {
+    Peaceful,
+    Random,
+    Aggressive,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Character {
+    pub name: String,
+    pub difficulty: Difficulty,
+    pub aggression: f32,
+    /// Ids of the [`TagDefinition`](crate::roster::TagDefinition)s this character has.
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "PairRules::is_empty")]
+    pub pair_rules: PairRules,
+    #[serde(default, skip_serializing_if = "Relationships::is_empty")]
+    pub relationships: Relationships,
+}
+
+/// How a character relates to other characters, by name.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct PairRules {
+    /// Characters that have to be in the lineup whenever this one is.
+    pub requires: BTreeSet<String>,
+    /// Characters that may never be in a lineup together with this one.
+    pub excludes: BTreeSet<String>,
+    /// Characters that are more likely to be drawn together with this one, and the
+    /// other way around.
+    pub prefers_with: BTreeSet<String>,
+}
+
+impl PairRules {
+    pub fn is_empty(&self) -> bool {
+        self.requires.is_empty() && self.excludes.is_empty() && self.prefers_with.is_empty()
+    }
+
+    pub fn names_mut(&mut self, kind: PairKind) -> &mut BTreeSet<String> {
+        match kind {
+            PairKind::Requires => &mut self.requires,
+            PairKind::Excludes => &mut self.excludes,
+            PairKind::PrefersWith => &mut self.prefers_with,
+        }
+    }
+
+    /// Every rule as a kind and the other character's name.
+    pub fn iter(&self) -> impl Iterator<Item = (PairKind, &String)> {
+        self.requires
+            .iter()
+            .map(|name| (PairKind::Requires, name))
+            .chain(self.excludes.iter().map(|name| (PairKind::Excludes, name)))
+            .chain(
+                self.prefers_with
+                    .iter()
+                    .map(|name| (PairKind::PrefersWith, name)),
+            )
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum PairKind {
+    Requires,
+    Excludes,
+    PrefersWith,
+}
+
+impl PairKind {
+    pub const ALL: [PairKind; 3] = [
+        PairKind::Requires,
+        PairKind::Excludes,
+        PairKind::PrefersWith,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PairKind::Requires => "requires",
+            PairKind::Excludes => "excludes",
+            PairKind::PrefersWith => "prefers",
+        }
+    }
+}
+
+/// Who a character's goals are about, by name.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct Relationships {
+    /// Characters this one hunts.
+    pub targets: BTreeSet<String>,
+    pub rivals: BTreeSet<String>,
+    pub protects: BTreeSet<String>,
+}
+
+impl Relationships {
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.rivals.is_empty() && self.protects.is_empty()
+    }
+
+    pub fn names_mut(&mut self, kind: RelationshipKind) -> &mut BTreeSet<String> {
+        match kind {
+            RelationshipKind::Targets => &mut self.targets,
+            RelationshipKind::Rivals => &mut self.rivals,
+            RelationshipKind::Protects => &mut self.protects,
+        }
+    }
+
+    /// Every relationship as a kind and the other character's name.
+    pub fn iter(&self) -> impl Iterator<Item = (RelationshipKind, &String)> {
+        self.targets
+            .iter()
+            .map(|name| (RelationshipKind::Targets, name))
+            .chain(
+                self.rivals
+                    .iter()
+                    .map(|name| (RelationshipKind::Rivals, name)),
+            )
+            .chain(
+                self.protects
+                    .iter()
+                    .map(|name| (RelationshipKind::Protects, name)),
+            )
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum RelationshipKind {
+    Targets,
+    Rivals,
+    Protects,
+}
+
+impl RelationshipKind {
+    pub const ALL: [RelationshipKind; 3] = [
+        RelationshipKind::Targets,
+        RelationshipKind::Rivals,
+        RelationshipKind::Protects,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelationshipKind::Targets => "targets",
+            RelationshipKind::Rivals => "rivals",
+            RelationshipKind::Protects => "protects",
+        }
+    }
+}
+
+impl Eq for Character {}
+
+impl Ord for Character {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl PartialOrd for Character {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Character {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Character {
+    /// Unlike `==`, which only compares names, this checks every attribute.
+    pub fn same_definition(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.difficulty == other.difficulty
+            && self.aggression == other.aggression
+            && self.tags == other.tags
+            && self.pair_rules == other.pair_rules
+            && self.relationships == other.relationships
+    }
+
+    pub fn has_tag(&self, id: &str) -> bool {
+        self.tags.contains(id)
+    }
+
+    /// Whether this character's rules allow it to be in a lineup with exactly the
+    /// characters `in_lineup` says are in it.
+    pub fn pair_rules_allow(&self, in_lineup: impl Fn(&str) -> bool) -> bool {
+        self.pair_rules.requires.iter().all(|name| in_lineup(name))
+            && !self.pair_rules.excludes.iter().any(|name| in_lineup(name))
+    }
+}
+
+/// Whether both lists hold the same characters in the same order, attribute for attribute.
+pub fn same_characters(a: &[Character], b: &[Character]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_definition(b))
+}
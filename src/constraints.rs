@@ -0,0 +1,170 @@
+use crate::character::{Character, Difficulty};
+use crate::roster::Roster;
+use crate::settings::{AggressionRange, DrawSettings, TagBound};
+
+/// How one composition rule fared for a lineup.
+#[derive(serde::Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// What the lineup actually has, e.g. "3 (wanted 2–4)".
+    pub detail: String,
+}
+
+pub fn average_aggression(lineup: &[Character]) -> f32 {
+    if lineup.is_empty() {
+        return 0.0;
+    }
+    lineup
+        .iter()
+        .map(|character| character.aggression)
+        .sum::<f32>()
+        / lineup.len() as f32
+}
+
+/// Evaluates every rule enabled in `settings` against `lineup`.
+pub fn check_lineup(lineup: &[Character], roster: &Roster, settings: &DrawSettings) -> Vec<Check> {
+    let tag_counts = roster.tag_counts(lineup);
+    let mut checks = vec![];
+
+    for tag in &roster.tags {
+        let bound = settings.tag_bound(&tag.id);
+        if bound == TagBound::default() {
+            continue;
+        }
+
+        let count = tag_counts[&tag.id];
+        checks.push(Check {
+            name: format!("{} {}", tag.icon, tag.label),
+            passed: bound.allows(count),
+            detail: format!("{} (wanted {})", count, bound.describe()),
+        });
+    }
+
+    for difficulty in Difficulty::ALL {
+        // Covers the quotas and, in strict mode, the maximum difficulty.
+        let bound = settings.difficulty_bound(difficulty);
+        if bound == TagBound::default() {
+            continue;
+        }
+
+        let count = lineup
+            .iter()
+            .filter(|character| character.difficulty == difficulty)
+            .count();
+        checks.push(Check {
+            name: difficulty.name().to_string(),
+            passed: bound.allows(count),
+            detail: format!("{} (wanted {})", count, bound.describe()),
+        });
+    }
+
+    for character in lineup {
+        let drawn = |name: &String| lineup.iter().any(|other| other.name == *name);
+        for name in &character.pair_rules.requires {
+            checks.push(Check {
+                name: format!("{} requires {}", character.name, name),
+                passed: drawn(name),
+                detail: format!("{} {}", name, if drawn(name) { "drawn" } else { "missing" }),
+            });
+        }
+        for name in &character.pair_rules.excludes {
+            checks.push(Check {
+                name: format!("{} excludes {}", character.name, name),
+                passed: !drawn(name),
+                detail: format!(
+                    "{} {}",
+                    name,
+                    if drawn(name) { "drawn" } else { "not drawn" }
+                ),
+            });
+        }
+    }
+
+    let drawn = |name: &str| lineup.iter().any(|character| character.name == name);
+    for rule in &settings.relationship_rules {
+        let unhappy: Vec<&str> = lineup
+            .iter()
+            .filter(|character| !rule.allows(character, drawn))
+            .map(|character| character.name.as_str())
+            .collect();
+        checks.push(Check {
+            name: rule.name().to_string(),
+            passed: unhappy.is_empty(),
+            detail: if unhappy.is_empty() {
+                "all targets in play".to_string()
+            } else {
+                format!("missing targets of {}", unhappy.join(", "))
+            },
+        });
+    }
+
+    let range = settings.aggression_range;
+    if range != AggressionRange::ANY {
+        let aggression = average_aggression(lineup);
+        checks.push(Check {
+            name: "Aggression".to_string(),
+            passed: range.contains(aggression),
+            detail: format!(
+                "average {:.2} (wanted {:.2}–{:.2})",
+                aggression, range.min, range.max
+            ),
+        });
+    }
+
+    checks
+}
+
+/// How hard it is to tell who is after a goal, from 0 (everyone's goal is obvious) to 1.
+///
+/// A goal shared by `n` drawn characters leaves a `1 / n` chance of guessing who holds
+/// it, so it scores `1 - 1 / n`; a goal held by a single character gives that character
+/// away completely. The score is the average over every goal in play, or `None` if no
+/// drawn character has a goal.
+pub fn goal_ambiguity(lineup: &[Character], roster: &Roster) -> Option<f32> {
+    let tag_counts = roster.tag_counts(lineup);
+    let scores: Vec<f32> = roster
+        .goals()
+        .map(|goal| tag_counts[&goal.id])
+        .filter(|count| *count > 0)
+        .map(|count| 1.0 - 1.0 / count as f32)
+        .collect();
+
+    if scores.is_empty() {
+        return None;
+    }
+    Some(scores.iter().sum::<f32>() / scores.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lineup(names: &[&str], roster: &Roster) -> Vec<Character> {
+        roster
+            .characters
+            .iter()
+            .filter(|character| names.contains(&character.name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn strict_maximum_difficulty_rejects_harder_characters() {
+        let roster = Roster::built_in();
+        let lineup = lineup(&["Legal", "Billionaire"], &roster);
+        let mut settings = DrawSettings {
+            preferred_maximum_difficulty: Difficulty::Easy,
+            ..DrawSettings::unbounded(2)
+        };
+        assert!(check_lineup(&lineup, &roster, &settings)
+            .iter()
+            .all(|check| check.passed));
+
+        settings.strict_maximum_difficulty = true;
+        let checks = check_lineup(&lineup, &roster, &settings);
+        let hard = checks.iter().find(|check| check.name == "Hard").unwrap();
+        assert!(!hard.passed);
+        assert_eq!(hard.detail, "2 (wanted at most 0)");
+    }
+}
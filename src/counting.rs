@@ -0,0 +1,594 @@
+use crate::character::{Character, Difficulty, PairKind};
+use crate::roster::Roster;
+use crate::settings::{AggressionRange, DifficultyQuota, DrawSettings, RelationshipRule, TagBound};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Aggression is summed in thousandths, so lineups with the same total share a state.
+const AGGRESSION_SCALE: f32 = 1000.0;
+
+/// Past this many partial lineups the rules are too tangled to count in reasonable time
+/// and memory.
+const MAX_STATES: usize = 1 << 20;
+
+/// How many partial lineups a count on the web may visit in all, since it runs within a
+/// single frame there.
+#[cfg(target_arch = "wasm32")]
+const WEB_BUDGET: usize = 1 << 18;
+
+/// How many different lineups the settings allow.
+pub struct LineupCount {
+    /// `None` when the rules are too tangled to count.
+    pub lineups: Option<u128>,
+    /// When there are no lineups: the rules that would allow some if they were dropped
+    /// on their own.
+    pub blocking: Vec<String>,
+}
+
+/// Counts every lineup of the pool that passes all rules in `settings`, and when there
+/// are none, works out which rules are to blame.
+pub fn count_lineups(pool: &[Character], roster: &Roster, settings: &DrawSettings) -> LineupCount {
+    count_lineups_within(pool, roster, settings, &mut Budget::unlimited())
+}
+
+/// How much work a count may do before it gives up.
+struct Budget {
+    /// Set by whoever started the count once they no longer need it.
+    cancelled: Arc<AtomicBool>,
+    /// How many more partial lineups may be visited.
+    states: usize,
+}
+
+impl Budget {
+    fn unlimited() -> Self {
+        Self {
+            cancelled: Arc::default(),
+            states: usize::MAX,
+        }
+    }
+
+    /// Uses up `states` partial lineups, and tells whether the count must stop.
+    fn spend(&mut self, states: usize) -> bool {
+        self.states = self.states.saturating_sub(states);
+        self.is_spent()
+    }
+
+    fn is_spent(&self) -> bool {
+        self.states == 0 || self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Like [`count_lineups`], but gives up on both the count and the blame once `budget` is
+/// spent.
+fn count_lineups_within(
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    budget: &mut Budget,
+) -> LineupCount {
+    let lineups = count(pool, roster, settings, budget);
+    if lineups != Some(0) {
+        return LineupCount {
+            lineups,
+            blocking: vec![],
+        };
+    }
+
+    let mut blocking = vec![];
+    let mut try_without = |rule: String, settings: &DrawSettings, pool: &[Character]| {
+        if budget.is_spent() {
+            return;
+        }
+        // A count that ran out of budget says nothing about the rule.
+        if count(pool, roster, settings, budget) != Some(0) && !budget.is_spent() {
+            blocking.push(rule);
+        }
+    };
+
+    for tag in &roster.tags {
+        if settings.tag_bound(&tag.id) != TagBound::default() {
+            let mut relaxed = settings.clone();
+            relaxed.tag_bounds.remove(&tag.id);
+            try_without(format!("{} {}", tag.icon, tag.label), &relaxed, pool);
+        }
+    }
+    for difficulty in Difficulty::ALL {
+        if settings.difficulty_quota(difficulty) != DifficultyQuota::default() {
+            let mut relaxed = settings.clone();
+            relaxed.difficulty_quotas.remove(&difficulty);
+            try_without(difficulty.name().to_string(), &relaxed, pool);
+        }
+    }
+    if settings.strict_maximum_difficulty {
+        let mut relaxed = settings.clone();
+        relaxed.strict_maximum_difficulty = false;
+        try_without("Strict maximum difficulty".to_string(), &relaxed, pool);
+    }
+    if settings.aggression_range != AggressionRange::ANY {
+        let mut relaxed = settings.clone();
+        relaxed.aggression_range = AggressionRange::ANY;
+        try_without("Aggression".to_string(), &relaxed, pool);
+    }
+    for rule in &settings.relationship_rules {
+        let mut relaxed = settings.clone();
+        relaxed.relationship_rules.remove(rule);
+        try_without(rule.name().to_string(), &relaxed, pool);
+    }
+    for (index, character) in pool.iter().enumerate() {
+        for (kind, name) in character.pair_rules.iter() {
+            if kind == PairKind::PrefersWith {
+                continue;
+            }
+            let mut relaxed = pool.to_vec();
+            relaxed[index].pair_rules.names_mut(kind).remove(name);
+            try_without(
+                format!("{} {} {}", character.name, kind.name(), name),
+                settings,
+                &relaxed,
+            );
+        }
+    }
+
+    LineupCount { lineups, blocking }
+}
+
+/// A count running on a thread of its own, for callers that can't wait for it. Dropping it
+/// cancels the count. On the web, where there are no threads, it counts straight away, but
+/// gives up past [`WEB_BUDGET`] partial lineups so the frame doesn't stall.
+pub struct PendingCount {
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: std::sync::mpsc::Receiver<LineupCount>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancelled: Arc<AtomicBool>,
+    result: Option<LineupCount>,
+}
+
+impl PendingCount {
+    pub fn start(pool: Vec<Character>, roster: Roster, settings: DrawSettings) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut budget = Budget::unlimited();
+            let cancelled = budget.cancelled.clone();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let count = count_lineups_within(&pool, &roster, &settings, &mut budget);
+                // Nobody is listening any more if the settings changed in the meantime.
+                let _ = sender.send(count);
+            });
+            Self {
+                receiver,
+                cancelled,
+                result: None,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let mut budget = Budget {
+                states: WEB_BUDGET,
+                ..Budget::unlimited()
+            };
+            Self {
+                result: Some(count_lineups_within(&pool, &roster, &settings, &mut budget)),
+            }
+        }
+    }
+
+    /// The count, once it is done.
+    pub fn result(&mut self) -> Option<&LineupCount> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.result.is_none() {
+            self.result = self.receiver.try_recv().ok();
+        }
+        self.result.as_ref()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for PendingCount {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A partial lineup, as far as the rules can tell it apart: its size, its capped count for
+/// every bound, its total aggression, and which of the characters it contains still have
+/// pair or relationship rules waiting to be checked.
+type State = (usize, Vec<usize>, i64, Vec<usize>);
+
+/// Counts lineups by walking the pool once and merging the partial lineups the rules can't
+/// tell apart, or returns `None` if there are too many of those or `budget` runs out.
+///
+/// Characters with "requires" or "excludes" rules, or with targets that a relationship
+/// rule looks at, go first, each group of linked characters together. A character is only
+/// remembered until it and everyone linked to it have been decided, so the state stays
+/// small however many rules there are, unless a lot of characters are linked to each other
+/// all at once.
+fn count(
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    budget: &mut Budget,
+) -> Option<u128> {
+    let size = settings.number_of_characters;
+
+    let index_of: HashMap<&str, usize> = pool
+        .iter()
+        .enumerate()
+        .map(|(index, character)| (character.name.as_str(), index))
+        .collect();
+    let in_pool = |names: &BTreeSet<String>| -> Vec<usize> {
+        names
+            .iter()
+            .filter_map(|name| index_of.get(name.as_str()).copied())
+            .collect()
+    };
+    let mut links: Vec<Link> = vec![];
+    for (index, character) in pool.iter().enumerate() {
+        let rules = &character.pair_rules;
+        for name in &rules.requires {
+            links.push(Link::any_of(index, index_of.get(name.as_str()).copied()));
+        }
+        for &other in &in_pool(&rules.excludes) {
+            links.push(Link {
+                character: index,
+                others: vec![other],
+                excludes: true,
+            });
+        }
+
+        let targets = &character.relationships.targets;
+        if targets.is_empty() {
+            continue;
+        }
+        for rule in &settings.relationship_rules {
+            match rule {
+                RelationshipRule::EveryTargetDrawn => {
+                    for name in targets {
+                        links.push(Link::any_of(index, index_of.get(name.as_str()).copied()));
+                    }
+                }
+                RelationshipRule::NoOrphanedHunters => links.push(Link {
+                    character: index,
+                    others: in_pool(targets),
+                    excludes: false,
+                }),
+            }
+        }
+    }
+
+    let mut neighbours: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); pool.len()];
+    for link in &links {
+        for &other in &link.others {
+            neighbours[link.character].insert(other);
+            neighbours[other].insert(link.character);
+        }
+    }
+    let linked: Vec<bool> = (0..pool.len())
+        .map(|index| {
+            !neighbours[index].is_empty() || links.iter().any(|link| link.character == index)
+        })
+        .collect();
+
+    // Linked characters in breadth-first order, so each group is decided in one go.
+    let mut order = vec![];
+    let mut placed = vec![false; pool.len()];
+    for start in 0..pool.len() {
+        if placed[start] || !linked[start] {
+            continue;
+        }
+        placed[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(index) = queue.pop_front() {
+            order.push(index);
+            for &other in &neighbours[index] {
+                if !placed[other] {
+                    placed[other] = true;
+                    queue.push_back(other);
+                }
+            }
+        }
+    }
+    order.extend((0..pool.len()).filter(|index| !placed[*index]));
+    let mut position = vec![0; pool.len()];
+    for (at, &index) in order.iter().enumerate() {
+        position[index] = at;
+    }
+
+    // A link is checked once every character it involves is decided, and a character is
+    // forgotten once every link that involves it is checked.
+    let mut checks_at: Vec<Vec<&Link>> = vec![vec![]; pool.len()];
+    let mut forget_position: Vec<usize> = position.clone();
+    for link in &links {
+        let at = link
+            .others
+            .iter()
+            .map(|&other| position[other])
+            .fold(position[link.character], usize::max);
+        checks_at[at].push(link);
+        for &index in link.others.iter().chain([&link.character]) {
+            forget_position[index] = forget_position[index].max(at);
+        }
+    }
+    let mut forgotten_at: Vec<Vec<usize>> = vec![vec![]; pool.len()];
+    for index in (0..pool.len()).filter(|index| linked[*index]) {
+        forgotten_at[forget_position[index]].push(index);
+    }
+
+    let mut bounds: Vec<TagBound> = vec![];
+    let mut counted_by: Vec<Vec<usize>> = vec![vec![]; pool.len()];
+
+    for tag in &roster.tags {
+        let bound = settings.tag_bound(&tag.id);
+        if bound == TagBound::default() {
+            continue;
+        }
+        for (index, character) in pool.iter().enumerate() {
+            if character.has_tag(&tag.id) {
+                counted_by[index].push(bounds.len());
+            }
+        }
+        bounds.push(bound);
+    }
+    for difficulty in Difficulty::ALL {
+        let bound = settings.difficulty_bound(difficulty);
+        if bound == TagBound::default() {
+            continue;
+        }
+        for (index, character) in pool.iter().enumerate() {
+            if character.difficulty == difficulty {
+                counted_by[index].push(bounds.len());
+            }
+        }
+        bounds.push(bound);
+    }
+
+    // Past these counts a bound can't tell lineups apart any more.
+    let caps: Vec<usize> = bounds
+        .iter()
+        .map(|bound| bound.max.map_or(bound.min, |max| max.saturating_add(1)))
+        .collect();
+    let range = settings.aggression_range;
+    let track_aggression = range != AggressionRange::ANY;
+
+    let mut states: HashMap<State, u128> =
+        HashMap::from([((0, vec![0; caps.len()], 0, vec![]), 1)]);
+    for (at, &index) in order.iter().enumerate() {
+        let character = &pool[index];
+        let remaining = pool.len() - at - 1;
+        let mut next = HashMap::with_capacity(states.len());
+        for ((chosen, counts, aggression, drawn), ways) in states {
+            // Leaving the character out, unless the rest of the pool can't fill the lineup.
+            if chosen + remaining >= size {
+                let entry = next
+                    .entry((chosen, counts.clone(), aggression, drawn.clone()))
+                    .or_insert(0u128);
+                *entry = entry.saturating_add(ways);
+            }
+            if chosen == size {
+                continue;
+            }
+
+            let mut counts = counts;
+            for &bound in &counted_by[index] {
+                counts[bound] = (counts[bound] + 1).min(caps[bound]);
+            }
+            let aggression = if track_aggression {
+                aggression + (character.aggression * AGGRESSION_SCALE).round() as i64
+            } else {
+                0
+            };
+            let mut drawn = drawn;
+            if linked[index] {
+                drawn.push(index);
+            }
+
+            let entry = next
+                .entry((chosen + 1, counts, aggression, drawn))
+                .or_default();
+            *entry = entry.saturating_add(ways);
+        }
+        states = next;
+
+        if !checks_at[at].is_empty() || !forgotten_at[at].is_empty() {
+            states = settle_links(states, &checks_at[at], &forgotten_at[at]);
+        }
+        if states.len() > MAX_STATES || budget.spend(states.len()) {
+            return None;
+        }
+    }
+
+    Some(
+        states
+            .into_iter()
+            .filter(|((chosen, counts, aggression, _), _)| {
+                *chosen == size
+                    && bounds
+                        .iter()
+                        .zip(counts)
+                        .all(|(bound, count)| bound.allows(*count))
+                    && (!track_aggression
+                        || range.contains(*aggression as f32 / AGGRESSION_SCALE / size as f32))
+            })
+            .fold(0, |total, (_, ways)| total.saturating_add(ways)),
+    )
+}
+
+/// Drops the partial lineups that break one of the `checked` links, then forgets whether
+/// the rest contain the `forgotten` characters.
+fn settle_links(
+    states: HashMap<State, u128>,
+    checked: &[&Link],
+    forgotten: &[usize],
+) -> HashMap<State, u128> {
+    let mut settled = HashMap::with_capacity(states.len());
+    for ((chosen, counts, aggression, mut drawn), ways) in states {
+        if !checked.iter().all(|link| link.allows(&drawn)) {
+            continue;
+        }
+
+        drawn.retain(|index| !forgotten.contains(index));
+        let entry = settled
+            .entry((chosen, counts, aggression, drawn))
+            .or_insert(0u128);
+        *entry = entry.saturating_add(ways);
+    }
+    settled
+}
+
+/// One "requires" or "excludes" rule or relationship rule of a character, by pool index:
+/// if the character is drawn, at least one of `others` must be, or with `excludes`, the
+/// one in `others` mustn't be.
+struct Link {
+    character: usize,
+    others: Vec<usize>,
+    excludes: bool,
+}
+
+impl Link {
+    /// A link to a character that must be drawn too, or that can't be when it isn't in the
+    /// pool.
+    fn any_of(character: usize, other: Option<usize>) -> Self {
+        Self {
+            character,
+            others: other.into_iter().collect(),
+            excludes: false,
+        }
+    }
+
+    fn allows(&self, drawn: &[usize]) -> bool {
+        !drawn.contains(&self.character)
+            || self.excludes != self.others.iter().any(|other| drawn.contains(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraints::check_lineup;
+
+    fn exclude(pool: &mut [Character], from: usize, to: usize) {
+        let name = pool[to].name.clone();
+        pool[from].pair_rules.excludes.insert(name);
+    }
+
+    fn binomial(n: u128, k: u128) -> u128 {
+        (0..k).fold(1, |total, i| total * (n - i) / (i + 1))
+    }
+
+    #[test]
+    fn agrees_with_checking_every_lineup() {
+        let roster = Roster::built_in();
+        let mut pool: Vec<Character> = roster.characters[..12].to_vec();
+        exclude(&mut pool, 0, 1);
+        exclude(&mut pool, 2, 7);
+        let required = pool[5].name.clone();
+        pool[3].pair_rules.requires.insert(required);
+        let target = pool[9].name.clone();
+        pool[4].relationships.targets.insert(target);
+        let targets = [pool[6].name.clone(), pool[10].name.clone()];
+        pool[8].relationships.targets.extend(targets);
+
+        for rule in [
+            RelationshipRule::EveryTargetDrawn,
+            RelationshipRule::NoOrphanedHunters,
+        ] {
+            let mut settings = DrawSettings {
+                number_of_characters: 5,
+                ..Default::default()
+            };
+            settings.relationship_rules.insert(rule);
+
+            let mut expected = 0;
+            for mask in 0u32..1 << pool.len() {
+                if mask.count_ones() as usize != settings.number_of_characters {
+                    continue;
+                }
+                let lineup: Vec<Character> = (0..pool.len())
+                    .filter(|index| mask & 1 << index != 0)
+                    .map(|index| pool[index].clone())
+                    .collect();
+                if check_lineup(&lineup, &roster, &settings)
+                    .iter()
+                    .all(|check| check.passed)
+                {
+                    expected += 1;
+                }
+            }
+
+            assert!(expected > 0);
+            assert_eq!(
+                count(&pool, &roster, &settings, &mut Budget::unlimited()),
+                Some(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn counts_many_pair_rules_quickly() {
+        let roster = Roster::built_in();
+        let size = roster.characters.len();
+
+        // Each character excludes the next: no two neighbours may be drawn together.
+        let mut chain = roster.characters.clone();
+        for index in 0..size - 1 {
+            exclude(&mut chain, index, index + 1);
+        }
+        assert_eq!(
+            count(
+                &chain,
+                &roster,
+                &DrawSettings::unbounded(12),
+                &mut Budget::unlimited()
+            ),
+            Some(binomial((size - 12 + 1) as u128, 12))
+        );
+
+        // One character excludes everyone else, so it can never be drawn.
+        let mut star = roster.characters.clone();
+        for index in 1..size {
+            exclude(&mut star, 0, index);
+        }
+        assert_eq!(
+            count(
+                &star,
+                &roster,
+                &DrawSettings::unbounded(12),
+                &mut Budget::unlimited()
+            ),
+            Some(binomial((size - 1) as u128, 12))
+        );
+    }
+
+    #[test]
+    fn gives_up_once_the_budget_is_spent() {
+        let roster = Roster::built_in();
+        let mut impossible = DrawSettings::unbounded(12);
+        impossible
+            .tag_bounds
+            .insert("officer".to_string(), TagBound::at_least(50));
+        assert!(!count_lineups(&roster.characters, &roster, &impossible)
+            .blocking
+            .is_empty());
+
+        let mut budget = Budget {
+            states: 10,
+            ..Budget::unlimited()
+        };
+        let spent = count_lineups_within(&roster.characters, &roster, &impossible, &mut budget);
+        assert_eq!(spent.lineups, None);
+        assert!(spent.blocking.is_empty());
+
+        let mut budget = Budget::unlimited();
+        budget.cancelled.store(true, Ordering::Relaxed);
+        assert_eq!(
+            count(
+                &roster.characters,
+                &roster,
+                &DrawSettings::unbounded(12),
+                &mut budget
+            ),
+            None
+        );
+    }
+}
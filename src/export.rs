@@ -0,0 +1,421 @@
+use crate::character::{Character, PairRules, Relationships};
+use crate::history::{self, HistoryEntry};
+use crate::persistence::{v1, v4, v5, OldHistoryEntry, OldPreset};
+use crate::roster::Roster;
+use crate::settings::{DrawSettings, Preset};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Bumped whenever the layout of [`ExportBundle`] changes in a way older builds can't read.
+///
+/// * 1: characters with a boolean per trait and goal.
+/// * 2: characters with a set of tags.
+/// * 3: per-tag count bounds in the draw settings.
+/// * 4: goal rules folded into the per-tag count bounds.
+/// * 5: a numeric aggression range instead of a preferred aggression level.
+pub const EXPORT_VERSION: u32 = 5;
+
+/// Everything a user may want to carry between devices.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct ExportBundle {
+    pub version: u32,
+    pub settings: DrawSettings,
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    #[serde(default)]
+    pub custom_characters: Vec<Character>,
+    /// Edited pair rules of built-in characters, by name.
+    #[serde(default)]
+    pub pair_rule_overrides: BTreeMap<String, PairRules>,
+    /// Edited relationships of built-in characters, by name.
+    #[serde(default)]
+    pub relationship_overrides: BTreeMap<String, Relationships>,
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+/// An older version of the bundle. `C` and `S` are the character and draw settings
+/// layouts it used.
+#[derive(serde::Deserialize)]
+#[serde(bound(deserialize = "C: serde::Deserialize<'de>, S: serde::Deserialize<'de>"))]
+struct LegacyBundle<C, S> {
+    settings: S,
+    #[serde(default)]
+    presets: Vec<OldPreset<S>>,
+    #[serde(default)]
+    custom_characters: Vec<C>,
+    #[serde(default)]
+    history: Vec<OldHistoryEntry<S>>,
+}
+
+impl<C: Into<Character>, S: Into<DrawSettings>> From<LegacyBundle<C, S>> for ExportBundle {
+    fn from(old: LegacyBundle<C, S>) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            settings: old.settings.into(),
+            presets: old.presets.into_iter().map(Into::into).collect(),
+            custom_characters: old.custom_characters.into_iter().map(Into::into).collect(),
+            pair_rule_overrides: BTreeMap::new(),
+            relationship_overrides: BTreeMap::new(),
+            history: old.history.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    InvalidCode,
+    InvalidJson(String),
+    UnsupportedVersion(u32),
+    InvalidData(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::InvalidCode => write!(f, "The code is not a valid export code."),
+            ImportError::InvalidJson(error) => write!(f, "Could not read export: {error}"),
+            ImportError::UnsupportedVersion(version) => write!(
+                f,
+                "Export version {version} was made by a newer version of the app (this one reads up to {EXPORT_VERSION})."
+            ),
+            ImportError::InvalidData(reason) => write!(f, "Export contains invalid data: {reason}"),
+        }
+    }
+}
+
+/// The parts of the current state an import is merged into.
+pub struct MergeTarget<'a> {
+    pub settings: &'a mut DrawSettings,
+    pub presets: &'a mut Vec<Preset>,
+    pub custom_characters: &'a mut Vec<Character>,
+    pub pair_rule_overrides: &'a mut BTreeMap<String, PairRules>,
+    pub relationship_overrides: &'a mut BTreeMap<String, Relationships>,
+    pub history: &'a mut Vec<HistoryEntry>,
+}
+
+/// What happened while merging an import into the current state.
+#[derive(Default)]
+pub struct ImportReport {
+    pub settings_replaced: bool,
+    pub presets_added: usize,
+    pub characters_added: usize,
+    pub pair_rules_added: usize,
+    pub relationships_added: usize,
+    pub history_added: usize,
+    pub conflicts: Vec<String>,
+}
+
+impl ExportBundle {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("export bundle is always serializable")
+    }
+
+    /// A single-line code that can be pasted into a chat message.
+    pub fn to_code(&self) -> String {
+        let json = serde_json::to_string(self).expect("export bundle is always serializable");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ImportError> {
+        let invalid_json = |error: serde_json::Error| ImportError::InvalidJson(error.to_string());
+
+        let probe: VersionProbe = serde_json::from_str(json).map_err(invalid_json)?;
+        if probe.version > EXPORT_VERSION {
+            return Err(ImportError::UnsupportedVersion(probe.version));
+        }
+
+        let bundle: Self = match probe.version {
+            1 => serde_json::from_str::<LegacyBundle<v1::Character, v1::DrawSettings>>(json)
+                .map_err(invalid_json)?
+                .into(),
+            2 => serde_json::from_str::<LegacyBundle<Character, v1::DrawSettings>>(json)
+                .map_err(invalid_json)?
+                .into(),
+            3 => serde_json::from_str::<LegacyBundle<Character, v4::DrawSettings>>(json)
+                .map_err(invalid_json)?
+                .into(),
+            4 => serde_json::from_str::<LegacyBundle<Character, v5::DrawSettings>>(json)
+                .map_err(invalid_json)?
+                .into(),
+            _ => serde_json::from_str(json).map_err(invalid_json)?,
+        };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    pub fn from_code(code: &str) -> Result<Self, ImportError> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = URL_SAFE_NO_PAD
+            .decode(code)
+            .map_err(|_| ImportError::InvalidCode)?;
+        let json = String::from_utf8(bytes).map_err(|_| ImportError::InvalidCode)?;
+        Self::from_json(&json)
+    }
+
+    fn validate(&self) -> Result<(), ImportError> {
+        if self.settings.number_of_characters == 0 {
+            return Err(ImportError::InvalidData(
+                "number of characters must be at least 1".to_string(),
+            ));
+        }
+
+        Roster::built_in()
+            .check_custom_characters(&self.custom_characters)
+            .map_err(ImportError::InvalidData)?;
+
+        for preset in &self.presets {
+            if preset.name.trim().is_empty() {
+                return Err(ImportError::InvalidData("a preset has no name".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Merges the bundle into the current state. Existing data always wins over
+    /// imported data, except for the draw settings when `replace_settings` is set; every
+    /// clash is listed in the returned report.
+    pub fn merge_into(
+        self,
+        target: MergeTarget<'_>,
+        built_in_characters: &[Character],
+        replace_settings: bool,
+    ) -> ImportReport {
+        let MergeTarget {
+            settings,
+            presets,
+            custom_characters,
+            pair_rule_overrides,
+            relationship_overrides,
+            history,
+        } = target;
+        let mut report = ImportReport::default();
+
+        if *settings != self.settings {
+            if replace_settings {
+                *settings = self.settings;
+                report.settings_replaced = true;
+            } else {
+                report
+                    .conflicts
+                    .push("The imported draw settings differ from yours; kept yours.".to_string());
+            }
+        }
+
+        for preset in self.presets {
+            match presets.iter().find(|existing| existing.name == preset.name) {
+                Some(existing) if existing.settings == preset.settings => {}
+                Some(_) => report.conflicts.push(format!(
+                    "Preset \"{}\" already exists with different settings; kept the existing one.",
+                    preset.name
+                )),
+                None => {
+                    presets.push(preset);
+                    report.presets_added += 1;
+                }
+            }
+        }
+
+        for character in self.custom_characters {
+            if built_in_characters.contains(&character) {
+                report.conflicts.push(format!(
+                    "Character \"{}\" has the same name as a built-in character; skipped it.",
+                    character.name
+                ));
+                continue;
+            }
+
+            match custom_characters
+                .iter()
+                .find(|existing| **existing == character)
+            {
+                Some(existing) if existing.same_definition(&character) => {}
+                Some(_) => report.conflicts.push(format!(
+                    "Character \"{}\" already exists with different attributes; kept the existing one.",
+                    character.name
+                )),
+                None => {
+                    custom_characters.push(character);
+                    report.characters_added += 1;
+                }
+            }
+        }
+
+        for (name, pair_rules) in self.pair_rule_overrides {
+            match pair_rule_overrides.get(&name) {
+                Some(existing) if *existing == pair_rules => {}
+                Some(_) => report.conflicts.push(format!(
+                    "Pair rules for \"{}\" were already edited differently; kept the existing ones.",
+                    name
+                )),
+                None => {
+                    pair_rule_overrides.insert(name, pair_rules);
+                    report.pair_rules_added += 1;
+                }
+            }
+        }
+
+        for (name, relationships) in self.relationship_overrides {
+            match relationship_overrides.get(&name) {
+                Some(existing) if *existing == relationships => {}
+                Some(_) => report.conflicts.push(format!(
+                    "Relationships of \"{}\" were already edited differently; kept the existing ones.",
+                    name
+                )),
+                None => {
+                    relationship_overrides.insert(name, relationships);
+                    report.relationships_added += 1;
+                }
+            }
+        }
+
+        for entry in self.history {
+            if !history.contains(&entry) {
+                history::record(history, entry);
+                report.history_added += 1;
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character::Difficulty;
+
+    use crate::settings::TagBound;
+
+    const V1: &str = include_str!("../tests/fixtures/export_v1.json");
+    const V2: &str = include_str!("../tests/fixtures/export_v2.json");
+    const V3: &str = include_str!("../tests/fixtures/export_v3.json");
+    const V4: &str = include_str!("../tests/fixtures/export_v4.json");
+
+    /// The state an import is merged into.
+    #[derive(Default)]
+    struct State {
+        settings: DrawSettings,
+        presets: Vec<Preset>,
+        custom_characters: Vec<Character>,
+        pair_rule_overrides: BTreeMap<String, PairRules>,
+        relationship_overrides: BTreeMap<String, Relationships>,
+        history: Vec<HistoryEntry>,
+    }
+
+    impl State {
+        fn merge(&mut self, bundle: ExportBundle, replace_settings: bool) -> ImportReport {
+            bundle.merge_into(
+                MergeTarget {
+                    settings: &mut self.settings,
+                    presets: &mut self.presets,
+                    custom_characters: &mut self.custom_characters,
+                    pair_rule_overrides: &mut self.pair_rule_overrides,
+                    relationship_overrides: &mut self.relationship_overrides,
+                    history: &mut self.history,
+                },
+                &Roster::built_in().characters,
+                replace_settings,
+            )
+        }
+    }
+
+    fn bundle() -> ExportBundle {
+        ExportBundle::from_json(V4).unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_codes_and_json() {
+        let json = bundle().to_json();
+
+        assert_eq!(
+            ExportBundle::from_code(&bundle().to_code())
+                .unwrap()
+                .to_json(),
+            json
+        );
+        assert_eq!(ExportBundle::from_json(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn reads_every_older_version() {
+        for json in [V1, V2, V3, V4] {
+            let bundle = ExportBundle::from_json(json).unwrap();
+
+            assert_eq!(bundle.version, EXPORT_VERSION);
+            assert_eq!(bundle.settings.number_of_characters, 14);
+            assert!(bundle.settings.preferred_maximum_difficulty == Difficulty::Medium);
+            assert!(bundle.settings.tag_bound("robot") == TagBound::at_least(1));
+            assert!(bundle.settings.tag_bound("officer") == TagBound::default());
+            assert!(bundle.settings.tag_bound("artifact") == TagBound::none_or_at_least(2));
+            assert_eq!(bundle.presets[0].name, "Teaching game");
+            assert!(bundle.presets[0].settings.tag_bound("officer") == TagBound::at_least(1));
+
+            let janitor = &bundle.custom_characters[0];
+            assert_eq!(janitor.name, "Janitor");
+            assert!(janitor.has_tag("briefcase") && janitor.has_tag("contamination"));
+            assert!(!janitor.has_tag("robot"));
+            assert_eq!(
+                bundle.history[0].characters,
+                ["Astrochimp", "Cyborg", "Janitor"]
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let json = V4.replacen("\"version\":4", "\"version\":99", 1);
+        assert!(matches!(
+            ExportBundle::from_json(&json),
+            Err(ImportError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn keeps_existing_data_on_conflicts() {
+        let mut state = State::default();
+        state.settings.number_of_characters = 17;
+        state.presets.push(Preset {
+            name: "Teaching game".to_string(),
+            settings: DrawSettings::default(),
+        });
+        let mut janitor = bundle().custom_characters.remove(0);
+        janitor.aggression = 1.0;
+        state.custom_characters.push(janitor);
+
+        let report = state.merge(bundle(), false);
+
+        assert!(!report.settings_replaced);
+        assert_eq!(state.settings.number_of_characters, 17);
+        assert_eq!(report.presets_added, 0);
+        assert!(state.presets[0].settings == DrawSettings::default());
+        assert_eq!(report.characters_added, 0);
+        assert_eq!(state.custom_characters[0].aggression, 1.0);
+        assert_eq!(report.history_added, 1);
+        assert_eq!(report.conflicts.len(), 3);
+
+        // Merging the same bundle twice adds nothing new.
+        let report = state.merge(bundle(), false);
+        assert_eq!(report.history_added, 0);
+    }
+
+    #[test]
+    fn replaces_settings_only_when_asked() {
+        let mut state = State::default();
+        let report = state.merge(bundle(), true);
+
+        assert!(report.settings_replaced);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(state.settings.number_of_characters, 14);
+        assert_eq!(report.presets_added, 1);
+        assert_eq!(report.characters_added, 1);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::character::Character;
+use crate::identity::{Deal, DealError, DealMode};
+use crate::randomizer::{self, DrawFailure};
+use crate::roster::Roster;
+use crate::settings::DrawSettings;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+/// A player's secret phrase, committed to before anything is drawn.
+#[derive(Clone)]
+pub struct Entry {
+    pub player: String,
+    pub phrase: String,
+    /// Random salt revealed along with `phrase`, so a short phrase can't be guessed from
+    /// its commitment.
+    pub nonce: String,
+    /// The salted hash of `phrase`, shown to everyone before the phrases are revealed.
+    pub commitment: String,
+}
+
+impl Entry {
+    pub fn new<R: Rng + ?Sized>(player: &str, phrase: &str, rng: &mut R) -> Self {
+        let nonce = to_hex(&rng.gen::<[u8; 16]>());
+        Self {
+            player: player.to_string(),
+            phrase: phrase.to_string(),
+            commitment: salted_commit(&nonce, phrase),
+            nonce,
+        }
+    }
+
+    /// Whether the revealed phrase and nonce hash to `commitment`, given in hex.
+    pub fn opens(&self, commitment: &str) -> bool {
+        salted_commit(&self.nonce, &self.phrase).eq_ignore_ascii_case(commitment.trim())
+    }
+}
+
+/// The SHA-256 hash of `phrase`, in hex.
+pub fn commit(phrase: &str) -> String {
+    to_hex(&Sha256::digest(phrase.as_bytes()))
+}
+
+/// Combines every player's phrase into one seed. Each phrase is prefixed with its length,
+/// so no two different lists of phrases give the same seed.
+pub fn seed(entries: &[Entry]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update((entry.phrase.len() as u64).to_le_bytes());
+        hasher.update(entry.phrase.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A salted commitment to an identity deal, published when the identities are dealt and
+/// opened after the game by revealing the salt.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Seal {
+    pub salt: String,
+    pub commitment: String,
+}
+
+impl Seal {
+    pub fn new<R: Rng + ?Sized>(assignment: &str, rng: &mut R) -> Self {
+        let salt = to_hex(&rng.gen::<[u8; 16]>());
+        Self {
+            commitment: salted_commit(&salt, assignment),
+            salt,
+        }
+    }
+
+    /// Whether `assignment` is the one the seal was made for.
+    pub fn opens(&self, assignment: &str) -> bool {
+        salted_commit(&self.salt, assignment) == self.commitment
+    }
+}
+
+/// The SHA-256 hash of `salt` followed by `text`, in hex. The salt keeps anyone from
+/// guessing the text by trying every possibility.
+pub fn salted_commit(salt: &str, text: &str) -> String {
+    commit(&format!("{salt}\n{text}"))
+}
+
+/// A lineup and identity deal drawn from the players' combined phrases, with everything
+/// needed to draw them again.
+#[derive(Clone)]
+pub struct FairDraw {
+    pub entries: Vec<Entry>,
+    pub pool: Vec<Character>,
+    pub settings: DrawSettings,
+    pub deal_mode: DealMode,
+    pub lineup: Vec<Character>,
+    pub deal: Result<Deal, DealError>,
+}
+
+/// The outcome of drawing a [`FairDraw`] again from its revealed phrases.
+pub struct Verification {
+    /// Whether each player's phrase matches the commitment they noted down, or `None` if
+    /// they noted none.
+    pub commitments: Vec<Option<bool>>,
+    pub lineup: bool,
+    pub deal: bool,
+}
+
+impl Verification {
+    /// Whether every commitment was checked against a noted one and matched, and the draw
+    /// came out the same again.
+    pub fn passed(&self) -> bool {
+        self.commitments
+            .iter()
+            .all(|matches| *matches == Some(true))
+            && self.lineup
+            && self.deal
+    }
+}
+
+impl FairDraw {
+    /// Draws a lineup from `pool` and deals one identity per entry, both from the seed the
+    /// entries' phrases make up.
+    pub fn new(
+        entries: Vec<Entry>,
+        pool: &[Character],
+        roster: &Roster,
+        settings: &DrawSettings,
+        deal_mode: DealMode,
+    ) -> Result<Self, DrawFailure> {
+        let (lineup, deal) = draw(&entries, pool, roster, settings, deal_mode)?;
+        Ok(Self {
+            entries,
+            pool: pool.to_vec(),
+            settings: settings.clone(),
+            deal_mode,
+            lineup,
+            deal,
+        })
+    }
+
+    pub fn seed(&self) -> [u8; 32] {
+        seed(&self.entries)
+    }
+
+    /// Checks the revealed phrases against the commitments, then draws again from them and
+    /// compares the result. `recorded` holds the commitments players noted down themselves,
+    /// in the order of the entries; phrases without one, or with a blank one, go unchecked.
+    pub fn verify(&self, roster: &Roster, recorded: &[String]) -> Verification {
+        let commitments = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                recorded
+                    .get(index)
+                    .filter(|noted| !noted.trim().is_empty())
+                    .map(|noted| entry.opens(noted))
+            })
+            .collect();
+        let redrawn = draw(
+            &self.entries,
+            &self.pool,
+            roster,
+            &self.settings,
+            self.deal_mode,
+        );
+
+        let (lineup, deal) = match redrawn {
+            Ok((lineup, deal)) => {
+                let deal = match (deal, &self.deal) {
+                    (Ok(redealt), Ok(dealt)) => redealt
+                        .seats
+                        .iter()
+                        .zip(&dealt.seats)
+                        .all(|(redealt, dealt)| redealt.options == dealt.options),
+                    (Err(_), Err(_)) => true,
+                    _ => false,
+                };
+                (lineup == self.lineup, deal)
+            }
+            Err(_) => (false, false),
+        };
+        Verification {
+            commitments,
+            lineup,
+            deal,
+        }
+    }
+}
+
+fn draw(
+    entries: &[Entry],
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    deal_mode: DealMode,
+) -> Result<(Vec<Character>, Result<Deal, DealError>), DrawFailure> {
+    let mut rng = ChaCha20Rng::from_seed(seed(entries));
+    let lineup = randomizer::draw(pool, roster, settings, &mut rng)?;
+    let deal = Deal::new(deal_mode, &lineup, entries.len(), &mut rng).map(|mut deal| {
+        for (seat, entry) in deal.seats.iter_mut().zip(entries) {
+            seat.player = entry.player.clone();
+        }
+        deal
+    });
+    Ok((lineup, deal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::ChaCha8Rng;
+
+    fn fair_draw() -> (FairDraw, Roster) {
+        let roster = Roster::built_in();
+        let settings = DrawSettings::unbounded(3);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let entries = vec![
+            Entry::new("Ann", "blue", &mut rng),
+            Entry::new("Bo", "blue", &mut rng),
+        ];
+        let draw = FairDraw::new(
+            entries,
+            &roster.characters,
+            &roster,
+            &settings,
+            DealMode::Random,
+        )
+        .ok()
+        .expect("the built-in roster has enough characters");
+        (draw, roster)
+    }
+
+    #[test]
+    fn equal_phrases_commit_differently() {
+        let (draw, _) = fair_draw();
+        assert_ne!(draw.entries[0].commitment, draw.entries[1].commitment);
+        assert_ne!(draw.entries[0].commitment, commit("blue"));
+    }
+
+    #[test]
+    fn tampered_reveal_fails_against_recorded_commitments() {
+        let (mut draw, roster) = fair_draw();
+        let recorded: Vec<String> = draw
+            .entries
+            .iter()
+            .map(|entry| entry.commitment.to_uppercase())
+            .collect();
+        assert!(draw.verify(&roster, &recorded).passed());
+
+        // A changed phrase and a replaced commitment shown in the app still fail against
+        // what the players noted down.
+        draw.entries[0].phrase = "red".to_string();
+        draw.entries[0].commitment = salted_commit(&draw.entries[0].nonce, "red");
+        let verification = draw.verify(&roster, &recorded);
+        assert_eq!(verification.commitments, [Some(false), Some(true)]);
+        assert!(!verification.passed());
+    }
+
+    #[test]
+    fn unnoted_commitments_are_not_checked() {
+        let (draw, roster) = fair_draw();
+        let recorded = [draw.entries[0].commitment.clone(), " ".to_string()];
+        let verification = draw.verify(&roster, &recorded);
+
+        assert_eq!(verification.commitments, [Some(true), None]);
+        assert!(!verification.passed());
+    }
+}
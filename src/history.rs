@@ -0,0 +1,43 @@
+use crate::character::Character;
+use crate::settings::DrawSettings;
+
+/// How many past lineups are kept before the oldest ones are dropped.
+pub const MAX_HISTORY_LEN: usize = 50;
+
+/// A lineup that was drawn, along with the settings it was drawn with.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct HistoryEntry {
+    pub characters: Vec<String>,
+    pub settings: DrawSettings,
+    /// Characters the players vetoed before settling on `characters`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vetoes: Vec<Veto>,
+}
+
+/// A player striking a character from the lineup.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct Veto {
+    pub player: String,
+    pub character: String,
+}
+
+impl HistoryEntry {
+    pub fn new(lineup: &[Character], settings: &DrawSettings) -> Self {
+        Self {
+            characters: lineup
+                .iter()
+                .map(|character| character.name.clone())
+                .collect(),
+            settings: settings.clone(),
+            vetoes: vec![],
+        }
+    }
+}
+
+/// Appends `entry`, dropping the oldest entries once the history is full.
+pub fn record(history: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    history.push(entry);
+    if history.len() > MAX_HISTORY_LEN {
+        history.drain(..history.len() - MAX_HISTORY_LEN);
+    }
+}
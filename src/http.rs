@@ -0,0 +1,74 @@
+//! Helpers shared by the HTTP servers.
+
+// Some helpers are only used by the room server, which comes with the GUI.
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
+use std::io::{self, Read};
+use tiny_http::{Header, Request, Response};
+
+pub type Reply = Response<io::Cursor<Vec<u8>>>;
+
+/// A request, read in full.
+pub struct Incoming {
+    pub path: String,
+    pub query: String,
+    pub body: String,
+}
+
+/// The largest request body read, in bytes. The servers answer one request at a time, so
+/// a huge body would hold up everyone else.
+pub const MAX_BODY_LEN: usize = 1 << 20;
+
+/// Reads the path, query and body of `request`, or the error to answer with when the body
+/// is too large or can't be read.
+pub fn read(request: &mut Request) -> Result<Incoming, Reply> {
+    let too_large = || error(413, "The request body is too large.");
+    if request
+        .body_length()
+        .map_or(false, |len| len > MAX_BODY_LEN)
+    {
+        return Err(too_large());
+    }
+
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_LEN as u64 + 1)
+        .read_to_string(&mut body)
+        .map_err(|_| error(400, "Could not read the request."))?;
+    if body.len() > MAX_BODY_LEN {
+        return Err(too_large());
+    }
+    Ok(Incoming { path, query, body })
+}
+
+/// The value of `name` in a query string. Values are never decoded.
+pub fn parameter<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+pub fn html(page: &str) -> Reply {
+    Response::from_string(page).with_header(header("Content-Type: text/html; charset=utf-8"))
+}
+
+pub fn json(status: u16, value: &impl serde::Serialize) -> Reply {
+    Response::from_string(serde_json::to_string(value).expect("responses are always serializable"))
+        .with_status_code(status)
+        .with_header(header("Content-Type: application/json"))
+}
+
+pub fn error(status: u16, message: &str) -> Reply {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+fn header(header: &str) -> Header {
+    header.parse().expect("headers are valid")
+}
@@ -0,0 +1,342 @@
+use crate::character::Character;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt;
+
+/// The most identities a player gets to choose from in a draft.
+pub const MAX_DRAFT_OPTIONS: usize = 3;
+
+/// The fewest identities a player gets to choose from in a draft.
+pub const MIN_DRAFT_OPTIONS: usize = 2;
+
+/// How identities are handed out to the players.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy)]
+pub enum DealMode {
+    /// Every player is dealt one identity at random.
+    Random,
+    /// Every player chooses one identity from a private pool of candidates.
+    Draft,
+}
+
+impl DealMode {
+    pub const ALL: [DealMode; 2] = [DealMode::Random, DealMode::Draft];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DealMode::Random => "Random",
+            DealMode::Draft => "Draft",
+        }
+    }
+}
+
+/// One player's share of the deal.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Seat {
+    pub player: String,
+    /// The identities the player may choose from, by name.
+    pub options: Vec<String>,
+    pub identity: Option<String>,
+}
+
+/// Identities handed out to the players of a lineup. Characters nobody holds are
+/// unclaimed.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Deal {
+    pub seats: Vec<Seat>,
+    /// The lineup the identities were dealt from, by name.
+    pub lineup: Vec<String>,
+}
+
+/// Why identities couldn't be dealt.
+#[derive(Clone)]
+pub enum DealError {
+    TooFewPlayers,
+    /// The lineup has fewer characters than the deal hands out.
+    TooFewCharacters {
+        available: usize,
+        needed: usize,
+    },
+}
+
+impl fmt::Display for DealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DealError::TooFewPlayers => write!(f, "There must be at least one player."),
+            DealError::TooFewCharacters { available, needed } => write!(
+                f,
+                "The deal needs {needed} characters but the lineup has only {available}."
+            ),
+        }
+    }
+}
+
+impl Deal {
+    /// Deals `players` identities from `lineup`, at random or as draft pools.
+    pub fn new<R: Rng + ?Sized>(
+        mode: DealMode,
+        lineup: &[Character],
+        players: usize,
+        rng: &mut R,
+    ) -> Result<Self, DealError> {
+        match mode {
+            DealMode::Random => Self::random(lineup, players, rng),
+            DealMode::Draft => Self::draft(lineup, players, rng),
+        }
+    }
+
+    /// Deals every player one identity from `lineup` at random.
+    pub fn random<R: Rng + ?Sized>(
+        lineup: &[Character],
+        players: usize,
+        rng: &mut R,
+    ) -> Result<Self, DealError> {
+        check_size(lineup, players, 1)?;
+
+        let seats = lineup
+            .choose_multiple(rng, players)
+            .enumerate()
+            .map(|(index, character)| Seat {
+                player: player_name(index),
+                options: vec![character.name.clone()],
+                identity: Some(character.name.clone()),
+            })
+            .collect();
+        Ok(Self {
+            seats,
+            lineup: names(lineup),
+        })
+    }
+
+    /// Deals every player a private pool of two or three identities from `lineup` to
+    /// choose from. The pools don't overlap, and each mixes easy and hard characters as
+    /// evenly as the lineup allows.
+    pub fn draft<R: Rng + ?Sized>(
+        lineup: &[Character],
+        players: usize,
+        rng: &mut R,
+    ) -> Result<Self, DealError> {
+        let options = (lineup.len() / players.max(1)).clamp(MIN_DRAFT_OPTIONS, MAX_DRAFT_OPTIONS);
+        check_size(lineup, players, options)?;
+
+        // Characters are sorted by difficulty and dealt back and forth, so that whoever
+        // gets the easiest character of a round gets the hardest of the next.
+        let mut characters: Vec<&Character> = lineup.iter().collect();
+        characters.shuffle(rng);
+        // Stable, so characters of the same difficulty stay shuffled.
+        characters.sort_by_key(|character| character.difficulty);
+
+        // Spread the characters over the whole range of difficulties, rather than dealing
+        // out only the easiest ones.
+        let dealt = players * options;
+        let characters: Vec<&Character> = (0..dealt)
+            .map(|index| characters[index * characters.len() / dealt])
+            .collect();
+
+        let mut order: Vec<usize> = (0..players).collect();
+        order.shuffle(rng);
+
+        let mut seats: Vec<Seat> = (0..players)
+            .map(|index| Seat {
+                player: player_name(index),
+                options: vec![],
+                identity: None,
+            })
+            .collect();
+        for (round, characters) in characters.chunks(players).enumerate() {
+            for (turn, character) in characters.iter().enumerate() {
+                let seat = if round % 2 == 0 {
+                    order[turn]
+                } else {
+                    order[players - 1 - turn]
+                };
+                seats[seat].options.push(character.name.clone());
+            }
+        }
+        for seat in &mut seats {
+            seat.options.sort();
+        }
+
+        Ok(Self {
+            seats,
+            lineup: names(lineup),
+        })
+    }
+
+    /// Settles the identity of the player at `seat`, if `name` is one of their options.
+    pub fn choose(&mut self, seat: usize, name: &str) {
+        if let Some(seat) = self.seats.get_mut(seat) {
+            if seat.options.iter().any(|option| option == name) {
+                seat.identity = Some(name.to_string());
+            }
+        }
+    }
+
+    /// Whether `other` hands the same options to the same players from the same lineup,
+    /// whatever they have chosen so far.
+    pub fn deals_same_options(&self, other: &Deal) -> bool {
+        self.lineup == other.lineup
+            && self.seats.len() == other.seats.len()
+            && self
+                .seats
+                .iter()
+                .zip(&other.seats)
+                .all(|(seat, other)| seat.player == other.player && seat.options == other.options)
+    }
+
+    /// Whether every player has an identity.
+    pub fn is_complete(&self) -> bool {
+        self.seats.iter().all(|seat| seat.identity.is_some())
+    }
+
+    /// Who holds which identity, one player per line.
+    pub fn assignment(&self) -> String {
+        self.seats
+            .iter()
+            .map(|seat| {
+                format!(
+                    "{}: {}",
+                    seat.player,
+                    seat.identity.as_deref().unwrap_or("")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The characters of the lineup nobody has claimed (yet), including every option
+    /// players passed on.
+    pub fn unclaimed(&self) -> Vec<&String> {
+        self.lineup
+            .iter()
+            .filter(|name| {
+                !self
+                    .seats
+                    .iter()
+                    .any(|seat| seat.identity.as_ref() == Some(name))
+            })
+            .collect()
+    }
+}
+
+fn check_size(lineup: &[Character], players: usize, options: usize) -> Result<(), DealError> {
+    if players == 0 {
+        return Err(DealError::TooFewPlayers);
+    }
+    if lineup.len() < players * options {
+        return Err(DealError::TooFewCharacters {
+            available: lineup.len(),
+            needed: players * options,
+        });
+    }
+    Ok(())
+}
+
+fn player_name(index: usize) -> String {
+    format!("Player {}", index + 1)
+}
+
+fn names(lineup: &[Character]) -> Vec<String> {
+    lineup
+        .iter()
+        .map(|character| character.name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character::Difficulty;
+    use crate::roster::Roster;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::BTreeSet;
+
+    fn of_difficulty(roster: &Roster, difficulty: Difficulty, count: usize) -> Vec<Character> {
+        roster
+            .characters
+            .iter()
+            .filter(|character| character.difficulty == difficulty)
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn draft_pools_are_disjoint_and_two_or_three_long() {
+        let lineup = Roster::built_in().characters[..15].to_vec();
+        for players in 1..=7 {
+            for seed in 0..10 {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                let deal = Deal::draft(&lineup, players, &mut rng).ok().unwrap();
+
+                assert_eq!(deal.seats.len(), players);
+                let mut dealt = BTreeSet::new();
+                for seat in &deal.seats {
+                    assert!((MIN_DRAFT_OPTIONS..=MAX_DRAFT_OPTIONS).contains(&seat.options.len()));
+                    assert!(seat.identity.is_none());
+                    for option in &seat.options {
+                        assert!(dealt.insert(option.clone()), "{option} dealt twice");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draft_pools_mix_difficulties() {
+        let roster = Roster::built_in();
+        let lineup: Vec<Character> = Difficulty::ALL
+            .into_iter()
+            .flat_map(|difficulty| of_difficulty(&roster, difficulty, 3))
+            .collect();
+        let difficulty = |name: &String| {
+            lineup
+                .iter()
+                .find(|character| character.name == *name)
+                .unwrap()
+                .difficulty
+        };
+
+        for seed in 0..10 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let deal = Deal::draft(&lineup, 3, &mut rng).ok().unwrap();
+            for seat in &deal.seats {
+                let mut difficulties: Vec<Difficulty> =
+                    seat.options.iter().map(difficulty).collect();
+                difficulties.sort();
+                assert!(difficulties == Difficulty::ALL);
+            }
+        }
+    }
+
+    #[test]
+    fn draft_needs_two_characters_per_player() {
+        let lineup = Roster::built_in().characters[..5].to_vec();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        assert!(matches!(
+            Deal::draft(&lineup, 3, &mut rng),
+            Err(DealError::TooFewCharacters {
+                available: 5,
+                needed: 6
+            })
+        ));
+        assert!(matches!(
+            Deal::draft(&lineup, 0, &mut rng),
+            Err(DealError::TooFewPlayers)
+        ));
+    }
+
+    #[test]
+    fn passed_options_stay_unclaimed() {
+        let lineup = Roster::built_in().characters[..6].to_vec();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut deal = Deal::draft(&lineup, 2, &mut rng).ok().unwrap();
+        let chosen = deal.seats[0].options[0].clone();
+        deal.choose(0, &chosen);
+
+        assert_eq!(deal.seats[0].identity.as_ref(), Some(&chosen));
+        assert_eq!(deal.unclaimed().len(), 5);
+        assert!(!deal.unclaimed().contains(&&chosen));
+    }
+}
@@ -0,0 +1,65 @@
+//! JavaScript bindings, for embedding lineup generation in a web page without the GUI.
+//!
+//! Every function takes and returns plain objects in the layouts documented in
+//! `docs/api.md`, and throws `{"error": ...}` objects where the JSON API would answer with
+//! an error.
+
+use crate::roster::Roster;
+use crate::service::{self, Query, ServiceError};
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+
+/// The built-in roster: every tag and character.
+#[wasm_bindgen]
+pub fn roster() -> Result<JsValue, JsValue> {
+    to_js(&Roster::built_in())
+}
+
+/// Draws a lineup. Throws a draw failure when no lineup passes every rule.
+#[wasm_bindgen]
+pub fn draw(query: JsValue) -> Result<JsValue, JsValue> {
+    let query = from_js(query)?;
+    service::draw(&query, &Roster::built_in())
+        .map_err(to_js_error)
+        .and_then(|report| to_js(&report))
+}
+
+/// Checks `query.lineup`, by name, against the settings.
+#[wasm_bindgen]
+pub fn validate(query: JsValue) -> Result<JsValue, JsValue> {
+    let query = from_js(query)?;
+    service::validate(&query, &Roster::built_in())
+        .map_err(to_js_error)
+        .and_then(|report| to_js(&report))
+}
+
+/// Counts the lineups the settings allow, and when there are none, the rules to blame.
+#[wasm_bindgen]
+pub fn feasibility(query: JsValue) -> Result<JsValue, JsValue> {
+    let query = from_js(query)?;
+    service::feasibility(&query, &Roster::built_in())
+        .map_err(to_js_error)
+        .and_then(|feasibility| to_js(&feasibility))
+}
+
+/// Reads a query from a plain object. `undefined` and `null` are the same as `{}`.
+fn from_js(query: JsValue) -> Result<Query, JsValue> {
+    if query.is_undefined() || query.is_null() {
+        return Ok(Query::default());
+    }
+    let json: String = JSON::stringify(&query)?.into();
+    service::parse_query(&json).map_err(to_js_error)
+}
+
+fn to_js(value: &impl serde::Serialize) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).expect("answers are always serializable");
+    JSON::parse(&json)
+}
+
+fn to_js_error(error: ServiceError) -> JsValue {
+    let thrown = match error {
+        ServiceError::BadRequest(message) => to_js(&serde_json::json!({ "error": message })),
+        ServiceError::NoLineup(failure) => to_js(&failure),
+    };
+    thrown.unwrap_or_else(|error| error)
+}
@@ -1,4 +1,37 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+#[cfg(not(target_arch = "wasm32"))]
+mod api;
+#[cfg(feature = "gui")]
 mod app;
+pub mod character;
+pub mod constraints;
+pub mod counting;
+#[cfg(feature = "gui")]
+mod export;
+pub mod fairness;
+pub mod history;
+#[cfg(not(target_arch = "wasm32"))]
+mod http;
+pub mod identity;
+#[cfg(all(feature = "js", target_arch = "wasm32"))]
+mod js;
+pub mod matching;
+#[cfg(feature = "gui")]
+mod persistence;
+pub mod randomizer;
+#[cfg(all(feature = "gui", not(target_arch = "wasm32")))]
+mod room;
+pub mod roster;
+pub mod scoring;
+#[cfg(any(feature = "js", not(target_arch = "wasm32")))]
+pub mod service;
+pub mod settings;
+#[cfg(feature = "gui")]
+pub mod widgets;
+#[cfg(not(target_arch = "wasm32"))]
+pub use api::ApiServer;
+#[cfg(feature = "gui")]
 pub use app::TemplateApp;
+#[cfg(feature = "gui")]
+pub use widgets::{DrawSettingsEditor, LineupView};
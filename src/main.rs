@@ -1,22 +1,210 @@
 #![warn(clippy::all, rust_2018_idioms)]
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
+// Hide the console window on Windows in release. Builds without the GUI are command-line
+// tools and keep it.
+#![cfg_attr(
+    all(not(debug_assertions), feature = "gui"),
+    windows_subsystem = "windows"
+)]
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
-fn main() -> eframe::Result<()> {
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        attach_console();
+    }
+
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
+    // `serve [ADDRESS]` runs the JSON API instead of the GUI.
+    if args.first().map(String::as_str) == Some("serve") {
+        let address = args.get(1).map_or("127.0.0.1:8080", String::as_str);
+        match stationfall_randomizer::ApiServer::bind(address) {
+            Ok(server) => {
+                if let Some(address) = server.address() {
+                    println!("Listening on http://{}", address);
+                }
+                server.run();
+            }
+            Err(error) => {
+                eprintln!("Could not listen on {}: {}", address, error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("validate") {
+        std::process::exit(validate(&args[1..]));
+    }
+
+    run_gui();
+}
+
+/// `validate [--settings FILE] [--file FILE] [NAME...]` checks a lineup typed out by hand,
+/// from the names given, a file, or else standard input. The settings file holds draw
+/// settings as JSON, as in the JSON API. Returns the exit code: 1 if the lineup breaks the
+/// rules, 2 if it couldn't be read.
+#[cfg(not(target_arch = "wasm32"))]
+fn validate(args: &[String]) -> i32 {
+    use stationfall_randomizer::matching::TypedLineup;
+    use stationfall_randomizer::roster::Roster;
+    use stationfall_randomizer::service::{self, Query, ServiceError};
+    use stationfall_randomizer::settings::DrawSettings;
+    use std::io::Read;
+
+    let read = |path: &str| {
+        std::fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))
+    };
+    let mut settings = DrawSettings::default();
+    let mut text = String::new();
+    let mut names = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let loaded = match arg.as_str() {
+            "--settings" => args
+                .next()
+                .ok_or("--settings needs a file".to_string())
+                .and_then(|path| {
+                    serde_json::from_str(&read(path)?)
+                        .map(|loaded| settings = loaded)
+                        .map_err(|error| {
+                            format!("Could not read the settings in {}: {}", path, error)
+                        })
+                }),
+            "--file" => args
+                .next()
+                .ok_or("--file needs a file".to_string())
+                .and_then(|path| read(path))
+                .map(|contents| text.push_str(&contents)),
+            _ => {
+                names.push(arg.as_str());
+                Ok(())
+            }
+        };
+        if let Err(error) = loaded {
+            eprintln!("{}", error);
+            return 2;
+        }
+    }
+    text.push('\n');
+    text.push_str(&names.join("\n"));
+    if text.trim().is_empty() && std::io::stdin().read_to_string(&mut text).is_err() {
+        eprintln!("Could not read the lineup from standard input.");
+        return 2;
+    }
+
+    let roster = Roster::built_in();
+    let typed = TypedLineup::read(&text, &roster.characters);
+    for note in typed.notes() {
+        println!("{}", note);
+    }
+    if !typed.is_resolved() {
+        return 2;
+    }
+
+    let query = Query {
+        lineup: typed
+            .lineup()
+            .into_iter()
+            .map(|character| character.name)
+            .collect(),
+        settings,
+        ..Default::default()
+    };
+    let report = match service::validate(&query, &roster) {
+        Ok(report) => report,
+        Err(ServiceError::BadRequest(message)) => {
+            eprintln!("{}", message);
+            return 2;
+        }
+        Err(ServiceError::NoLineup(failure)) => {
+            eprintln!("{}", failure.error);
+            return 2;
+        }
+    };
+
+    println!("Lineup: {}", report.lineup.join(", "));
+    for check in &report.checks {
+        println!(
+            "{} {}: {}",
+            if check.passed { "✔" } else { "✘" },
+            check.name,
+            check.detail
+        );
+    }
+    println!("Average aggression: {:.2}", report.average_aggression);
+    match report.goal_ambiguity {
+        Some(ambiguity) => println!("Goal ambiguity: {:.0}%", ambiguity * 100.0),
+        None => println!("Goal ambiguity: no goals in play"),
+    }
+    println!("Balance score: {:.2}", report.score.total);
+    for part in &report.score.parts {
+        println!(
+            "    {}: {:.2} (weight {:.2})",
+            part.name, part.score, part.weight
+        );
+    }
+
+    if report.lineup.len() != query.settings.number_of_characters {
+        println!(
+            "The settings call for {} characters, not {}.",
+            query.settings.number_of_characters,
+            report.lineup.len()
+        );
+    }
+    if report.valid {
+        println!("The lineup passes every rule.");
+        0
+    } else {
+        println!("The lineup breaks the rules.");
+        1
+    }
+}
+
+/// Release builds with the GUI have no console of their own, so the command-line modes
+/// write to the console they were started from, if any.
+#[cfg(all(windows, not(debug_assertions), feature = "gui"))]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails harmlessly when there is no console to attach to, e.g. when started from Explorer.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(all(windows, not(debug_assertions), feature = "gui"))
+))]
+fn attach_console() {}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "gui"))]
+fn run_gui() {
     let native_options = eframe::NativeOptions::default();
-    eframe::run_native(
+    let result = eframe::run_native(
         "eframe template",
         native_options,
         Box::new(|cc| Box::new(stationfall_randomizer::TemplateApp::new(cc))),
-    )
+    );
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "gui")))]
+fn run_gui() {
+    eprintln!("Built without the `gui` feature; only `serve` and `validate` are available.");
+    std::process::exit(2);
 }
 
 // when compiling to web using trunk.
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", feature = "gui"))]
 fn main() {
     // Make sure panics are logged using `console.error`.
     console_error_panic_hook::set_once();
@@ -36,3 +224,7 @@ fn main() {
         .expect("failed to start eframe");
     });
 }
+
+// The web build without the GUI is only a library; see the `js` feature.
+#[cfg(all(target_arch = "wasm32", not(feature = "gui")))]
+fn main() {}
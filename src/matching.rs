@@ -0,0 +1,274 @@
+//! Reading lineups that people typed out, e.g. in a forum post: a list of names, matched
+//! against the roster even when they are abbreviated or misspelled.
+
+use crate::character::Character;
+
+/// What a name from a typed list was matched to.
+pub enum NameMatch<'a> {
+    /// The name, ignoring case, spaces and punctuation.
+    Exact(&'a Character),
+    /// Part of a name, or a small misspelling.
+    Close(&'a Character),
+    /// Several characters match equally well.
+    Ambiguous(Vec<&'a Character>),
+    Unknown,
+}
+
+pub struct MatchedName<'a> {
+    /// The name as it was typed.
+    pub given: String,
+    pub found: NameMatch<'a>,
+}
+
+/// A typed list of names and what each one was matched to.
+pub struct TypedLineup<'a> {
+    pub names: Vec<MatchedName<'a>>,
+}
+
+impl<'a> TypedLineup<'a> {
+    /// Reads names from `text` (see [`parse_names`]) and matches each against `pool`.
+    pub fn read(text: &str, pool: &'a [Character]) -> Self {
+        Self {
+            names: parse_names(text)
+                .into_iter()
+                .map(|given| MatchedName {
+                    found: match_name(&given, pool),
+                    given,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether every name matched exactly one character.
+    pub fn is_resolved(&self) -> bool {
+        self.names
+            .iter()
+            .all(|name| matches!(name.found, NameMatch::Exact(_) | NameMatch::Close(_)))
+    }
+
+    /// The matched characters in the order they were typed, each only once.
+    pub fn lineup(&self) -> Vec<Character> {
+        let mut lineup: Vec<Character> = vec![];
+        for name in &self.names {
+            if let NameMatch::Exact(character) | NameMatch::Close(character) = name.found {
+                if !lineup.contains(character) {
+                    lineup.push(character.clone());
+                }
+            }
+        }
+        lineup
+    }
+
+    /// A line for every name that wasn't an exact match, and every character typed twice.
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = vec![];
+        let mut seen: Vec<&str> = vec![];
+        for name in &self.names {
+            match &name.found {
+                NameMatch::Exact(character) | NameMatch::Close(character) => {
+                    if let NameMatch::Close(_) = name.found {
+                        notes.push(format!("Read \"{}\" as {}.", name.given, character.name));
+                    }
+                    if seen.contains(&character.name.as_str()) {
+                        notes.push(format!("{} is listed more than once.", character.name));
+                    }
+                    seen.push(&character.name);
+                }
+                NameMatch::Ambiguous(characters) => {
+                    let names: Vec<&str> = characters
+                        .iter()
+                        .map(|character| character.name.as_str())
+                        .collect();
+                    notes.push(format!(
+                        "\"{}\" could be {}.",
+                        name.given,
+                        names.join(" or ")
+                    ));
+                }
+                NameMatch::Unknown => {
+                    notes.push(format!("\"{}\" matches no character.", name.given));
+                }
+            }
+        }
+        notes
+    }
+}
+
+/// The names in a pasted list: one per line, or separated by commas or semicolons. List
+/// markers such as `1.`, `-` or `*` are dropped, as are blank entries.
+pub fn parse_names(text: &str) -> Vec<String> {
+    text.split(['\n', ',', ';'])
+        .map(|entry| {
+            entry
+                .trim()
+                .trim_start_matches(|c: char| {
+                    c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*' | '•' | '#')
+                })
+                .trim()
+                .to_string()
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Matches one typed name against `pool`. Exact matches win, then names or words that start
+/// with it, then names that contain it, then the names closest in spelling.
+pub fn match_name<'a>(name: &str, pool: &'a [Character]) -> NameMatch<'a> {
+    let given = key(name);
+    if given.is_empty() {
+        return NameMatch::Unknown;
+    }
+    if let Some(character) = pool.iter().find(|character| key(&character.name) == given) {
+        return NameMatch::Exact(character);
+    }
+
+    // Short abbreviations match too much to be useful.
+    if given.len() >= 3 {
+        let starting: Vec<&Character> = pool
+            .iter()
+            .filter(|character| {
+                key(&character.name).starts_with(&given)
+                    || character
+                        .name
+                        .split_whitespace()
+                        .any(|word| key(word).starts_with(&given))
+            })
+            .collect();
+        if !starting.is_empty() {
+            return close_or_ambiguous(starting);
+        }
+    }
+    if given.len() >= 4 {
+        let containing: Vec<&Character> = pool
+            .iter()
+            .filter(|character| key(&character.name).contains(&given))
+            .collect();
+        if !containing.is_empty() {
+            return close_or_ambiguous(containing);
+        }
+    }
+
+    // Allow roughly one typo for every four letters.
+    let allowed = (given.chars().count() / 4).clamp(1, 3);
+    let distances: Vec<(usize, &Character)> = pool
+        .iter()
+        .map(|character| (edit_distance(&given, &key(&character.name)), character))
+        .collect();
+    match distances.iter().map(|(distance, _)| *distance).min() {
+        Some(best) if best <= allowed => close_or_ambiguous(
+            distances
+                .into_iter()
+                .filter(|(distance, _)| *distance == best)
+                .map(|(_, character)| character)
+                .collect(),
+        ),
+        _ => NameMatch::Unknown,
+    }
+}
+
+fn close_or_ambiguous(mut characters: Vec<&Character>) -> NameMatch<'_> {
+    if characters.len() == 1 {
+        NameMatch::Close(characters.remove(0))
+    } else {
+        NameMatch::Ambiguous(characters)
+    }
+}
+
+/// A name in lowercase with only its letters and digits, so "station-chief" and
+/// "Station Chief" compare equal.
+fn key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How many letters must be inserted, removed or replaced to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roster::Roster;
+
+    fn matched(name: &str) -> Vec<String> {
+        let pool = Roster::built_in().characters;
+        match match_name(name, &pool) {
+            NameMatch::Exact(character) | NameMatch::Close(character) => {
+                vec![character.name.clone()]
+            }
+            NameMatch::Ambiguous(characters) => characters
+                .into_iter()
+                .map(|character| character.name.clone())
+                .collect(),
+            NameMatch::Unknown => vec![],
+        }
+    }
+
+    #[test]
+    fn parses_pasted_lists() {
+        let text = "1. Astrochimp\n2) Station Chief, Colonel;\n\n- Telepathic Rat\n* Cyborg";
+        assert_eq!(
+            parse_names(text),
+            [
+                "Astrochimp",
+                "Station Chief",
+                "Colonel",
+                "Telepathic Rat",
+                "Cyborg"
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_abbreviations_and_typos() {
+        assert_eq!(matched("station-chief"), ["Station Chief"]);
+        assert_eq!(matched("chimp"), ["Astrochimp"]);
+        assert_eq!(matched("rat"), ["Telepathic Rat"]);
+        assert_eq!(matched("Microbiolgist"), ["Microbiologist"]);
+        assert_eq!(matched("Colonol"), ["Colonel"]);
+    }
+
+    #[test]
+    fn reports_ambiguous_and_unknown_names() {
+        let mut pool = Roster::built_in().characters;
+        let mut astronaut = pool[0].clone();
+        astronaut.name = "Astronaut".to_string();
+        pool.push(astronaut);
+
+        let astro = match_name("Astro", &pool);
+        assert!(matches!(astro, NameMatch::Ambiguous(characters) if characters.len() == 2));
+        assert!(matched("Co").is_empty());
+        assert!(matched("Zookeeper").is_empty());
+    }
+
+    #[test]
+    fn notes_corrections_and_repeats() {
+        let pool = Roster::built_in().characters;
+        let typed = TypedLineup::read("Astrochimp, chimp, Colonol, Zookeeper", &pool);
+
+        assert!(!typed.is_resolved());
+        assert_eq!(typed.lineup().len(), 2);
+        assert_eq!(
+            typed.notes(),
+            [
+                "Read \"chimp\" as Astrochimp.",
+                "Astrochimp is listed more than once.",
+                "Read \"Colonol\" as Colonel.",
+                "\"Zookeeper\" matches no character.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,649 @@
+//! Loading of saved app state across schema versions.
+//!
+//! Adding a field with a sensible default doesn't need a new version: `#[serde(default)]`
+//! takes care of it. Anything that renames, moves or reinterprets saved data bumps
+//! [`SCHEMA_VERSION`] and adds a step to [`upgrade`], with a frozen copy of the old layout
+//! in a `vN` module so the step keeps compiling as the live types evolve.
+
+use crate::history::HistoryEntry;
+use crate::settings::{DrawSettings, Preset};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use std::fmt;
+
+/// The version written by this build.
+///
+/// * 0: the original flat layout, with the draw settings directly on the app.
+/// * 1: settings nested under `settings`, plus presets, custom characters and history.
+/// * 2: adds `schema_version` and fixes the spelling of `wants_contamination`.
+/// * 3: replaces the per-trait booleans on characters with a set of tags.
+/// * 4: replaces "at least one officer/robot" with per-tag count bounds.
+/// * 5: replaces "at least two characters per goal" with bounds on each goal tag.
+/// * 6: replaces the preferred aggression level with a numeric range.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// Storage key under which state that couldn't be loaded is kept, so it isn't lost when
+/// the app saves over it. It stays there until it is restored or discarded.
+pub const BACKUP_KEY: &str = "unreadable_app_state";
+
+#[derive(Debug)]
+pub enum LoadError {
+    Parse(String),
+    NewerVersion(u32),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Parse(error) => write!(f, "Could not read saved state: {error}"),
+            LoadError::NewerVersion(version) => write!(
+                f,
+                "Saved state is from a newer version of the app (schema {version}, this build reads up to {SCHEMA_VERSION})."
+            ),
+        }
+    }
+}
+
+impl From<ron::error::SpannedError> for LoadError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        LoadError::Parse(error.to_string())
+    }
+}
+
+impl From<ron::Error> for LoadError {
+    fn from(error: ron::Error) -> Self {
+        LoadError::Parse(error.to_string())
+    }
+}
+
+/// Reads a saved blob of any known schema version into the current layout.
+pub fn load<T: DeserializeOwned>(blob: &str) -> Result<T, LoadError> {
+    let mut version = detect_version(blob)?;
+    if version > SCHEMA_VERSION {
+        return Err(LoadError::NewerVersion(version));
+    }
+
+    let mut blob = blob.to_string();
+    while version < SCHEMA_VERSION {
+        blob = upgrade(version, blob)?;
+        version += 1;
+    }
+
+    Ok(ron::from_str(&blob)?)
+}
+
+/// Just enough of every layout to tell them apart.
+#[derive(serde::Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    schema_version: u32,
+    #[serde(default)]
+    settings: Option<IgnoredAny>,
+}
+
+fn detect_version(blob: &str) -> Result<u32, LoadError> {
+    let probe: VersionProbe = ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(blob)?;
+
+    Ok(match probe {
+        VersionProbe {
+            schema_version: 0,
+            settings: None,
+        } => 0,
+        VersionProbe {
+            schema_version: 0,
+            settings: Some(_),
+        } => 1,
+        VersionProbe { schema_version, .. } => schema_version,
+    })
+}
+
+/// Turns a blob of schema `version` into one of schema `version + 1`.
+fn upgrade(version: u32, blob: String) -> Result<String, LoadError> {
+    Ok(match version {
+        0 => ron::to_string(&v1::State::from(ron::from_str::<v0::State>(&blob)?))?,
+        // Only the spelling of `wants_contamination` changed, which serde accepts as an alias.
+        1 => blob,
+        2 => ron::to_string(&v3::State::from(ron::from_str::<v1::State>(&blob)?))?,
+        3 => ron::to_string(&v4::State::from(ron::from_str::<v3::State>(&blob)?))?,
+        4 => ron::to_string(&v5::State::from(ron::from_str::<v4::State>(&blob)?))?,
+        5 => ron::to_string(&v6::State::from(ron::from_str::<v5::State>(&blob)?))?,
+        _ => unreachable!("no upgrade from schema version {version}"),
+    })
+}
+
+mod v0 {
+    use crate::character::{AggressionLevel, Difficulty};
+
+    #[derive(serde::Deserialize)]
+    #[serde(default)]
+    pub struct State {
+        pub number_of_characters: usize,
+        pub at_least_one_robot: bool,
+        pub at_least_one_officer: bool,
+        pub at_least_two_agents_per_goal: bool,
+        pub preferred_maximum_difficulty: Difficulty,
+        pub preferred_aggression_level: AggressionLevel,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl Default for State {
+        fn default() -> Self {
+            Self {
+                number_of_characters: 15,
+                at_least_one_robot: true,
+                at_least_one_officer: true,
+                at_least_two_agents_per_goal: true,
+                preferred_maximum_difficulty: Difficulty::Hard,
+                preferred_aggression_level: AggressionLevel::Random,
+                show_detailed_character_info: false,
+                found_character_list: true,
+            }
+        }
+    }
+}
+
+/// A preset whose settings use an older layout `S`.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(crate) struct OldPreset<S> {
+    pub name: String,
+    pub settings: S,
+}
+
+impl<S> OldPreset<S> {
+    fn upgrade<T: From<S>>(self) -> OldPreset<T> {
+        OldPreset {
+            name: self.name,
+            settings: self.settings.into(),
+        }
+    }
+}
+
+impl<S: Into<DrawSettings>> From<OldPreset<S>> for Preset {
+    fn from(old: OldPreset<S>) -> Self {
+        Self {
+            name: old.name,
+            settings: old.settings.into(),
+        }
+    }
+}
+
+/// A history entry whose settings use an older layout `S`.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(crate) struct OldHistoryEntry<S> {
+    pub characters: Vec<String>,
+    pub settings: S,
+}
+
+impl<S> OldHistoryEntry<S> {
+    fn upgrade<T: From<S>>(self) -> OldHistoryEntry<T> {
+        OldHistoryEntry {
+            characters: self.characters,
+            settings: self.settings.into(),
+        }
+    }
+}
+
+impl<S: Into<DrawSettings>> From<OldHistoryEntry<S>> for HistoryEntry {
+    fn from(old: OldHistoryEntry<S>) -> Self {
+        Self {
+            characters: old.characters,
+            settings: old.settings.into(),
+            vetoes: vec![],
+        }
+    }
+}
+
+/// Schema versions 1 and 2, which only differ in the spelling of `wants_contamination`
+/// and in whether `schema_version` is written.
+pub(crate) mod v1 {
+    use super::{v0, v4, OldHistoryEntry, OldPreset};
+    use crate::character::{self, AggressionLevel, Difficulty};
+    use crate::settings;
+    use std::collections::BTreeSet;
+
+    /// A character from before traits and goals became tags.
+    #[derive(serde::Deserialize, serde::Serialize)]
+    pub struct Character {
+        pub name: String,
+        pub difficulty: Difficulty,
+        pub aggression: f32,
+        pub is_robot: bool,
+        pub is_officer: bool,
+        pub cares_about_briefcase: bool,
+        pub cares_about_artifact: bool,
+        #[serde(alias = "wants_contamintaion")]
+        pub wants_contamination: bool,
+    }
+
+    impl From<Character> for character::Character {
+        fn from(old: Character) -> Self {
+            let tags = [
+                (old.is_officer, "officer"),
+                (old.is_robot, "robot"),
+                (old.cares_about_artifact, "artifact"),
+                (old.cares_about_briefcase, "briefcase"),
+                (old.wants_contamination, "contamination"),
+            ];
+
+            Self {
+                name: old.name,
+                difficulty: old.difficulty,
+                aggression: old.aggression,
+                tags: tags
+                    .into_iter()
+                    .filter(|(has_tag, _)| *has_tag)
+                    .map(|(_, tag)| tag.to_string())
+                    .collect::<BTreeSet<_>>(),
+                pair_rules: Default::default(),
+                relationships: Default::default(),
+            }
+        }
+    }
+
+    /// Draw settings from before trait counts became configurable.
+    #[derive(serde::Deserialize, serde::Serialize)]
+    #[serde(default)]
+    pub struct DrawSettings {
+        pub number_of_characters: usize,
+        pub at_least_one_robot: bool,
+        pub at_least_one_officer: bool,
+        pub at_least_two_agents_per_goal: bool,
+        pub preferred_maximum_difficulty: Difficulty,
+        pub preferred_aggression_level: AggressionLevel,
+    }
+
+    impl Default for DrawSettings {
+        fn default() -> Self {
+            Self {
+                number_of_characters: 15,
+                at_least_one_robot: true,
+                at_least_one_officer: true,
+                at_least_two_agents_per_goal: true,
+                preferred_maximum_difficulty: Difficulty::Hard,
+                preferred_aggression_level: AggressionLevel::Random,
+            }
+        }
+    }
+
+    impl From<DrawSettings> for settings::DrawSettings {
+        fn from(old: DrawSettings) -> Self {
+            v4::DrawSettings::from(old).into()
+        }
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize)]
+    pub struct State {
+        pub settings: DrawSettings,
+        pub presets: Vec<OldPreset<DrawSettings>>,
+        pub custom_characters: Vec<Character>,
+        pub history: Vec<OldHistoryEntry<DrawSettings>>,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl From<v0::State> for State {
+        fn from(old: v0::State) -> Self {
+            Self {
+                settings: DrawSettings {
+                    number_of_characters: old.number_of_characters,
+                    at_least_one_robot: old.at_least_one_robot,
+                    at_least_one_officer: old.at_least_one_officer,
+                    at_least_two_agents_per_goal: old.at_least_two_agents_per_goal,
+                    preferred_maximum_difficulty: old.preferred_maximum_difficulty,
+                    preferred_aggression_level: old.preferred_aggression_level,
+                },
+                presets: vec![],
+                custom_characters: vec![],
+                history: vec![],
+                show_detailed_character_info: old.show_detailed_character_info,
+                found_character_list: old.found_character_list,
+            }
+        }
+    }
+}
+
+mod v3 {
+    use super::{v1, OldHistoryEntry, OldPreset};
+    use crate::character::Character;
+
+    #[derive(serde::Deserialize, serde::Serialize)]
+    pub struct State {
+        pub settings: v1::DrawSettings,
+        pub presets: Vec<OldPreset<v1::DrawSettings>>,
+        pub custom_characters: Vec<Character>,
+        pub history: Vec<OldHistoryEntry<v1::DrawSettings>>,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl From<v1::State> for State {
+        fn from(old: v1::State) -> Self {
+            Self {
+                settings: old.settings,
+                presets: old.presets,
+                custom_characters: old.custom_characters.into_iter().map(Into::into).collect(),
+                history: old.history,
+                show_detailed_character_info: old.show_detailed_character_info,
+                found_character_list: old.found_character_list,
+            }
+        }
+    }
+}
+
+pub(crate) mod v4 {
+    use super::{convert, v1, v3, v5, OldHistoryEntry, OldPreset};
+    use crate::character::{AggressionLevel, Character, Difficulty};
+    use crate::settings::{self, TagBound};
+    use std::collections::BTreeMap;
+
+    /// Draw settings from before goal rules were configured per goal.
+    #[derive(serde::Deserialize, serde::Serialize)]
+    #[serde(default)]
+    pub struct DrawSettings {
+        pub number_of_characters: usize,
+        pub tag_bounds: BTreeMap<String, TagBound>,
+        pub at_least_two_agents_per_goal: bool,
+        pub preferred_maximum_difficulty: Difficulty,
+        pub preferred_aggression_level: AggressionLevel,
+    }
+
+    impl Default for DrawSettings {
+        fn default() -> Self {
+            v1::DrawSettings::default().into()
+        }
+    }
+
+    impl From<v1::DrawSettings> for DrawSettings {
+        fn from(old: v1::DrawSettings) -> Self {
+            let mut tag_bounds = BTreeMap::new();
+            if old.at_least_one_officer {
+                tag_bounds.insert("officer".to_string(), TagBound::at_least(1));
+            }
+            if old.at_least_one_robot {
+                tag_bounds.insert("robot".to_string(), TagBound::at_least(1));
+            }
+
+            Self {
+                number_of_characters: old.number_of_characters,
+                tag_bounds,
+                at_least_two_agents_per_goal: old.at_least_two_agents_per_goal,
+                preferred_maximum_difficulty: old.preferred_maximum_difficulty,
+                preferred_aggression_level: old.preferred_aggression_level,
+            }
+        }
+    }
+
+    impl From<DrawSettings> for settings::DrawSettings {
+        fn from(old: DrawSettings) -> Self {
+            v5::DrawSettings::from(old).into()
+        }
+    }
+
+    impl From<DrawSettings> for v5::DrawSettings {
+        fn from(old: DrawSettings) -> Self {
+            let mut tag_bounds = old.tag_bounds;
+
+            // "At least two characters per goal" meant that no goal may have exactly one
+            // character. Goals that already had their own bound keep it.
+            if old.at_least_two_agents_per_goal {
+                for goal in ["artifact", "briefcase", "contamination"] {
+                    tag_bounds
+                        .entry(goal.to_string())
+                        .or_insert_with(|| TagBound::none_or_at_least(2));
+                }
+            }
+
+            Self {
+                number_of_characters: old.number_of_characters,
+                tag_bounds,
+                preferred_maximum_difficulty: old.preferred_maximum_difficulty,
+                preferred_aggression_level: old.preferred_aggression_level,
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize)]
+    pub struct State {
+        pub settings: DrawSettings,
+        pub presets: Vec<OldPreset<DrawSettings>>,
+        pub custom_characters: Vec<Character>,
+        pub history: Vec<OldHistoryEntry<DrawSettings>>,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl From<v3::State> for State {
+        fn from(old: v3::State) -> Self {
+            Self {
+                settings: old.settings.into(),
+                presets: convert(old.presets, OldPreset::upgrade),
+                custom_characters: old.custom_characters,
+                history: convert(old.history, OldHistoryEntry::upgrade),
+                show_detailed_character_info: old.show_detailed_character_info,
+                found_character_list: old.found_character_list,
+            }
+        }
+    }
+}
+
+pub(crate) mod v5 {
+    use super::{convert, v4, OldHistoryEntry, OldPreset};
+    use crate::character::{AggressionLevel, Character, Difficulty};
+    use crate::settings::{self, TagBound};
+    use std::collections::BTreeMap;
+
+    /// Draw settings from before the aggression level became a numeric range.
+    #[derive(serde::Deserialize, serde::Serialize)]
+    #[serde(default)]
+    pub struct DrawSettings {
+        pub number_of_characters: usize,
+        pub tag_bounds: BTreeMap<String, TagBound>,
+        pub preferred_maximum_difficulty: Difficulty,
+        pub preferred_aggression_level: AggressionLevel,
+    }
+
+    impl Default for DrawSettings {
+        fn default() -> Self {
+            v4::DrawSettings::default().into()
+        }
+    }
+
+    impl From<DrawSettings> for settings::DrawSettings {
+        fn from(old: DrawSettings) -> Self {
+            Self {
+                number_of_characters: old.number_of_characters,
+                tag_bounds: old.tag_bounds,
+                preferred_maximum_difficulty: old.preferred_maximum_difficulty,
+                aggression_range: old.preferred_aggression_level.into(),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize)]
+    pub struct State {
+        pub settings: DrawSettings,
+        pub presets: Vec<OldPreset<DrawSettings>>,
+        pub custom_characters: Vec<Character>,
+        pub history: Vec<OldHistoryEntry<DrawSettings>>,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl From<v4::State> for State {
+        fn from(old: v4::State) -> Self {
+            Self {
+                settings: old.settings.into(),
+                presets: convert(old.presets, OldPreset::upgrade),
+                custom_characters: old.custom_characters,
+                history: convert(old.history, OldHistoryEntry::upgrade),
+                show_detailed_character_info: old.show_detailed_character_info,
+                found_character_list: old.found_character_list,
+            }
+        }
+    }
+}
+
+// The live types still read and write this layout; give it its own copies once they don't.
+mod v6 {
+    use super::{convert, v5};
+    use crate::character::Character;
+    use crate::history::HistoryEntry;
+    use crate::settings::{DrawSettings, Preset};
+
+    #[derive(serde::Serialize)]
+    pub struct State {
+        pub settings: DrawSettings,
+        pub presets: Vec<Preset>,
+        pub custom_characters: Vec<Character>,
+        pub history: Vec<HistoryEntry>,
+        pub show_detailed_character_info: bool,
+        pub found_character_list: bool,
+    }
+
+    impl From<v5::State> for State {
+        fn from(old: v5::State) -> Self {
+            Self {
+                settings: old.settings.into(),
+                presets: convert(old.presets, Into::into),
+                custom_characters: old.custom_characters,
+                history: convert(old.history, Into::into),
+                show_detailed_character_info: old.show_detailed_character_info,
+                found_character_list: old.found_character_list,
+            }
+        }
+    }
+}
+
+fn convert<T, U>(old: Vec<T>, f: impl FnMut(T) -> U) -> Vec<U> {
+    old.into_iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character::{AggressionLevel, Character, Difficulty};
+    use crate::fairness::Seal;
+    use crate::history::HistoryEntry;
+    use crate::identity::Deal;
+    use crate::settings::{AggressionRange, DrawSettings, Preset, TagBound};
+    use crate::TemplateApp;
+
+    /// The parts of the current layout the fixtures are checked against.
+    #[derive(serde::Deserialize)]
+    struct Current {
+        settings: DrawSettings,
+        presets: Vec<Preset>,
+        custom_characters: Vec<Character>,
+        history: Vec<HistoryEntry>,
+        show_detailed_character_info: bool,
+        found_character_list: bool,
+    }
+
+    const V0: &str = include_str!("../tests/fixtures/state_v0.ron");
+    const V1: &str = include_str!("../tests/fixtures/state_v1.ron");
+    const V2: &str = include_str!("../tests/fixtures/state_v2.ron");
+    const V3: &str = include_str!("../tests/fixtures/state_v3.ron");
+    const V4: &str = include_str!("../tests/fixtures/state_v4.ron");
+    const V5: &str = include_str!("../tests/fixtures/state_v5.ron");
+    const V6: &str = include_str!("../tests/fixtures/state_v6.ron");
+    /// Schema 6 with the fields added since, which don't need a version of their own.
+    const V6_DEAL: &str = include_str!("../tests/fixtures/state_v6_deal.ron");
+
+    #[test]
+    fn detects_every_version() {
+        assert_eq!(detect_version(V0).unwrap(), 0);
+        assert_eq!(detect_version(V1).unwrap(), 1);
+        assert_eq!(detect_version(V2).unwrap(), 2);
+        assert_eq!(detect_version(V3).unwrap(), 3);
+        assert_eq!(detect_version(V4).unwrap(), 4);
+        assert_eq!(detect_version(V5).unwrap(), 5);
+        assert_eq!(detect_version(V6).unwrap(), 6);
+        assert_eq!(detect_version(V6_DEAL).unwrap(), 6);
+    }
+
+    #[test]
+    fn migrates_flat_v0_settings() {
+        let state: Current = load(V0).unwrap();
+
+        assert_eq!(state.settings.number_of_characters, 17);
+        assert!(state.settings.tag_bound("officer").min == 1);
+        assert!(state.settings.tag_bound("robot").min == 0);
+        assert!(state.settings.tag_bound("artifact") == TagBound::default());
+        assert!(state.settings.preferred_maximum_difficulty == Difficulty::Easy);
+        assert!(state.settings.aggression_range == AggressionLevel::Aggressive.into());
+        assert!(state.presets.is_empty());
+        assert!(state.show_detailed_character_info);
+        assert!(!state.found_character_list);
+    }
+
+    #[test]
+    fn keeps_custom_data() {
+        for blob in [V1, V2, V3, V4, V5, V6, V6_DEAL] {
+            let state: Current = load(blob).unwrap();
+
+            assert_eq!(state.settings.number_of_characters, 14);
+            assert!(state.settings.tag_bound("robot").min == 1);
+            assert!(state.settings.tag_bound("officer").min == 0);
+            assert!(state.presets[0].settings.tag_bound("officer").min == 1);
+            assert!(state.settings.tag_bound("briefcase") == TagBound::none_or_at_least(2));
+            assert!(
+                state.settings.aggression_range == AggressionRange::from(AggressionLevel::Peaceful)
+            );
+            assert_eq!(state.presets.len(), 1);
+            assert_eq!(state.presets[0].name, "Teaching game");
+            assert_eq!(state.custom_characters.len(), 1);
+            let tags: Vec<&str> = state.custom_characters[0]
+                .tags
+                .iter()
+                .map(String::as_str)
+                .collect();
+            assert_eq!(tags, ["briefcase", "contamination"]);
+            assert_eq!(state.history.len(), 1);
+            assert_eq!(state.history[0].characters[2], "Janitor");
+        }
+    }
+
+    #[test]
+    fn keeps_the_sealed_deal() {
+        #[derive(serde::Deserialize)]
+        struct Sealed {
+            deal: Option<Deal>,
+            deal_seal: Option<Seal>,
+        }
+
+        let state: Sealed = load(V6_DEAL).unwrap();
+        let deal = state.deal.unwrap();
+        assert!(state.deal_seal.unwrap().opens(&deal.assignment()));
+
+        let state: Sealed = load(V6).unwrap();
+        assert!(state.deal.is_none() && state.deal_seal.is_none());
+    }
+
+    #[test]
+    fn fixtures_load_into_the_app() {
+        for blob in [V0, V1, V2, V3, V4, V5, V6, V6_DEAL] {
+            load::<TemplateApp>(blob).unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trips_current_state() {
+        let blob = ron::to_string(&TemplateApp::default()).unwrap();
+
+        assert_eq!(detect_version(&blob).unwrap(), SCHEMA_VERSION);
+        load::<TemplateApp>(&blob).unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let blob = format!("(schema_version:{})", SCHEMA_VERSION + 1);
+
+        assert!(matches!(
+            load::<TemplateApp>(&blob),
+            Err(LoadError::NewerVersion(_))
+        ));
+    }
+}
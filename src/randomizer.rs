@@ -0,0 +1,728 @@
+use crate::character::{same_characters, Character, Difficulty};
+use crate::constraints::check_lineup;
+use crate::history::Veto;
+use crate::roster::Roster;
+use crate::scoring::score;
+use crate::settings::DrawSettings;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{mpsc, Arc};
+
+/// How many candidate lineups are generated before giving up.
+pub const MAX_TRIES: usize = 1000;
+
+/// How many candidate lineups the per-tier inclusion estimates are based on.
+pub const INCLUSION_SAMPLES: usize = 2000;
+
+/// How many valid lineups are compared for each alternative when overlap is minimized.
+pub const OVERLAP_CANDIDATES: usize = 20;
+
+/// How many rounds in a row may turn up only lineups already offered before
+/// [`draw_alternatives`] settles for fewer.
+const MAX_REPEATS: usize = 5;
+
+/// How much more likely a character becomes once a character it is paired with by a
+/// "prefers" rule has been drawn.
+pub const PREFERRED_PAIR_BOOST: f32 = 3.0;
+
+/// How many full draws an [`InclusionEstimate`] runs before it is considered done.
+pub const INCLUSION_DRAWS: usize = 500;
+
+/// Why no lineup could be drawn.
+#[derive(Default)]
+pub struct DrawFailure {
+    /// Difficulty limits the pool can't satisfy at all. When there are any, no
+    /// candidates are drawn.
+    pub shortages: Vec<Shortage>,
+    /// How many candidates each rule rejected, keyed by the rule's name.
+    pub rejections: BTreeMap<String, usize>,
+}
+
+/// A difficulty quota or the strict maximum difficulty asks for more than the pool has.
+pub enum Shortage {
+    /// A quota needs more characters of `difficulty` than are allowed in the pool.
+    Tier {
+        difficulty: Difficulty,
+        available: usize,
+        needed: usize,
+    },
+    /// Strict mode leaves fewer characters than the lineup needs.
+    Strict {
+        maximum: Difficulty,
+        available: usize,
+        needed: usize,
+    },
+    /// The pool, or the quota maximums when `quotas` is set, leave fewer characters than
+    /// the lineup needs.
+    Capacity {
+        available: usize,
+        needed: usize,
+        quotas: bool,
+    },
+    /// The quota minimums add up to more than the lineup size.
+    Overbooked { minimum: usize, lineup_size: usize },
+    /// Too few characters are left to replace the vetoed ones.
+    Substitutes { available: usize, needed: usize },
+}
+
+impl fmt::Display for Shortage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shortage::Tier {
+                difficulty,
+                available,
+                needed,
+            } => write!(
+                f,
+                "The {} quota needs {needed} characters but only {available} are allowed; the tier would need {} more.",
+                difficulty.name(),
+                needed - available
+            ),
+            Shortage::Strict {
+                maximum,
+                available,
+                needed,
+            } => write!(
+                f,
+                "Strict mode allows only the {available} characters up to {}; the lineup would need {} more.",
+                maximum.name(),
+                needed - available
+            ),
+            Shortage::Capacity {
+                available,
+                needed,
+                quotas: true,
+            } => write!(
+                f,
+                "The difficulty quotas allow only {available} characters; the lineup would need {} more.",
+                needed - available
+            ),
+            Shortage::Capacity {
+                available,
+                needed,
+                quotas: false,
+            } => write!(
+                f,
+                "Only {available} characters can be drawn; the lineup would need {} more.",
+                needed - available
+            ),
+            Shortage::Overbooked {
+                minimum,
+                lineup_size,
+            } => write!(
+                f,
+                "The difficulty quotas ask for at least {minimum} characters but the lineup has only {lineup_size}."
+            ),
+            Shortage::Substitutes { available, needed } => write!(
+                f,
+                "Only {available} characters are left to replace {needed} vetoed ones."
+            ),
+        }
+    }
+}
+
+/// Every difficulty limit in `settings` that no lineup drawn from `pool` can meet.
+pub fn shortages(pool: &[Character], settings: &DrawSettings) -> Vec<Shortage> {
+    let needed = settings.number_of_characters;
+    let mut shortages = vec![];
+    let mut minimum = 0;
+    let mut capacity = 0;
+    let mut capped = false;
+
+    for difficulty in Difficulty::ALL {
+        let bound = settings.difficulty_bound(difficulty);
+        let in_pool = pool
+            .iter()
+            .filter(|character| character.difficulty == difficulty)
+            .count();
+        let available = bound.max.map_or(in_pool, |max| max.min(in_pool));
+
+        if bound.min > available {
+            shortages.push(Shortage::Tier {
+                difficulty,
+                available,
+                needed: bound.min,
+            });
+        }
+        minimum += bound.min;
+        capacity += available;
+        capped |= available < in_pool;
+    }
+
+    if minimum > needed {
+        shortages.push(Shortage::Overbooked {
+            minimum,
+            lineup_size: needed,
+        });
+    }
+
+    if capacity < needed {
+        let strict_capacity = pool
+            .iter()
+            .filter(|character| character.difficulty <= settings.preferred_maximum_difficulty)
+            .count();
+        if settings.strict_maximum_difficulty && strict_capacity < needed {
+            shortages.push(Shortage::Strict {
+                maximum: settings.preferred_maximum_difficulty,
+                available: strict_capacity,
+                needed,
+            });
+        } else {
+            shortages.push(Shortage::Capacity {
+                available: capacity,
+                needed,
+                quotas: capped,
+            });
+        }
+    }
+
+    shortages
+}
+
+/// Draws `settings.best_of` lineups that pass every rule in `settings` and returns the
+/// one with the best balance score.
+pub fn draw<R: Rng + ?Sized>(
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> Result<Vec<Character>, DrawFailure> {
+    let shortages = shortages(pool, settings);
+    if !shortages.is_empty() {
+        return Err(DrawFailure {
+            shortages,
+            ..Default::default()
+        });
+    }
+
+    let mut best = draw_valid(pool, roster, settings, rng)?;
+    let mut best_score = score(&best, pool, roster, settings).total;
+    for _ in 1..settings.best_of {
+        let Ok(lineup) = draw_valid(pool, roster, settings, rng) else {
+            break;
+        };
+        let lineup_score = score(&lineup, pool, roster, settings).total;
+        if lineup_score > best_score {
+            best = lineup;
+            best_score = lineup_score;
+        }
+    }
+
+    Ok(best)
+}
+
+/// Draws up to `settings.alternatives` different lineups. With `minimize_overlap`, each
+/// one is picked from several valid lineups as the one sharing the fewest characters with
+/// those before it.
+pub fn draw_alternatives<R: Rng + ?Sized>(
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> Result<Vec<Vec<Character>>, DrawFailure> {
+    let mut alternatives = vec![draw(pool, roster, settings, rng)?];
+    let candidates = if settings.minimize_overlap {
+        OVERLAP_CANDIDATES
+    } else {
+        1
+    };
+
+    let mut repeats = 0;
+    while alternatives.len() < settings.alternatives && repeats < MAX_REPEATS {
+        let overlap = |lineup: &Vec<Character>| {
+            alternatives
+                .iter()
+                .map(|other| {
+                    lineup
+                        .iter()
+                        .filter(|character| other.contains(character))
+                        .count()
+                })
+                .sum::<usize>()
+        };
+        let best = (0..candidates)
+            .filter_map(|_| draw(pool, roster, settings, rng).ok())
+            .filter(|lineup| !alternatives.contains(lineup))
+            .min_by_key(overlap);
+
+        match best {
+            Some(lineup) => {
+                alternatives.push(lineup);
+                repeats = 0;
+            }
+            None => repeats += 1,
+        }
+    }
+
+    Ok(alternatives)
+}
+
+/// Replaces every character of `lineup` struck by `vetoes` in a single pass, with
+/// substitutes from `pool` that keep every rule in `settings` satisfied. `vetoes` holds
+/// every veto of the round, including ones already replaced, so no character vetoed
+/// earlier in the round is drawn back as a substitute.
+pub fn replace_vetoed<R: Rng + ?Sized>(
+    lineup: &[Character],
+    vetoes: &[Veto],
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> Result<Vec<Character>, DrawFailure> {
+    let vetoed: BTreeSet<&str> = vetoes.iter().map(|veto| veto.character.as_str()).collect();
+    let kept: Vec<Character> = lineup
+        .iter()
+        .filter(|character| !vetoed.contains(character.name.as_str()))
+        .cloned()
+        .collect();
+    let open = lineup.len() - kept.len();
+    let substitutes: Vec<&Character> = pool
+        .iter()
+        .filter(|character| {
+            !vetoed.contains(character.name.as_str())
+                && !lineup.contains(character)
+                && settings.difficulty_bound(character.difficulty).max != Some(0)
+        })
+        .collect();
+
+    let mut failure = DrawFailure::default();
+    if substitutes.len() < open {
+        failure.shortages.push(Shortage::Substitutes {
+            available: substitutes.len(),
+            needed: open,
+        });
+        return Err(failure);
+    }
+
+    for _ in 0..MAX_TRIES {
+        let mut candidate = kept.clone();
+        let mut remaining = substitutes.clone();
+        while candidate.len() < lineup.len() {
+            let eligible: Vec<usize> = (0..remaining.len()).collect();
+            let pick = pick_weighted(&eligible, &remaining, &candidate, settings, rng);
+            candidate.push(remaining.swap_remove(pick).clone());
+        }
+
+        let checks = check_lineup(&candidate, roster, settings);
+        if checks.iter().all(|check| check.passed) {
+            candidate.sort();
+            return Ok(candidate);
+        }
+
+        for check in checks.into_iter().filter(|check| !check.passed) {
+            *failure.rejections.entry(check.name).or_default() += 1;
+        }
+    }
+
+    Err(failure)
+}
+
+/// Draws candidates until one passes every rule in `settings`.
+fn draw_valid<R: Rng + ?Sized>(
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> Result<Vec<Character>, DrawFailure> {
+    let mut failure = DrawFailure::default();
+
+    for _ in 0..MAX_TRIES {
+        let mut lineup = candidate(pool, settings, rng);
+        let checks = check_lineup(&lineup, roster, settings);
+
+        if checks.iter().all(|check| check.passed) {
+            lineup.sort();
+            return Ok(lineup);
+        }
+
+        for check in checks.into_iter().filter(|check| !check.passed) {
+            *failure.rejections.entry(check.name).or_default() += 1;
+        }
+    }
+
+    Err(failure)
+}
+
+/// A random lineup that meets the difficulty quotas and is otherwise drawn according to
+/// the difficulty weights and "prefers" pair rules.
+fn candidate<R: Rng + ?Sized>(
+    pool: &[Character],
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> Vec<Character> {
+    let bounds: BTreeMap<Difficulty, _> = Difficulty::ALL
+        .into_iter()
+        .map(|difficulty| (difficulty, settings.difficulty_bound(difficulty)))
+        .collect();
+    let mut counts: BTreeMap<Difficulty, usize> = BTreeMap::new();
+    let mut remaining: Vec<&Character> = pool.iter().collect();
+    let mut lineup: Vec<Character> = vec![];
+
+    // Every tier gets its minimum first, then the remaining slots are filled without going
+    // over any maximum. Each pick is weighted, so the order characters are drawn in matters.
+    for filling_minimums in [true, false] {
+        while lineup.len() < settings.number_of_characters {
+            let eligible: Vec<usize> = (0..remaining.len())
+                .filter(|&index| {
+                    let difficulty = remaining[index].difficulty;
+                    let count = counts.get(&difficulty).copied().unwrap_or(0);
+                    let bound = &bounds[&difficulty];
+                    if filling_minimums {
+                        count < bound.min
+                    } else {
+                        bound.max.map_or(true, |max| count < max)
+                    }
+                })
+                .collect();
+            if eligible.is_empty() {
+                break;
+            }
+
+            let pick = pick_weighted(&eligible, &remaining, &lineup, settings, rng);
+            let character = remaining.swap_remove(pick);
+            *counts.entry(character.difficulty).or_default() += 1;
+            lineup.push(character.clone());
+        }
+    }
+
+    lineup
+}
+
+/// Picks one of the `eligible` indices into `remaining`, weighted by how well each
+/// character fits into `lineup`.
+fn pick_weighted<R: Rng + ?Sized>(
+    eligible: &[usize],
+    remaining: &[&Character],
+    lineup: &[Character],
+    settings: &DrawSettings,
+    rng: &mut R,
+) -> usize {
+    let weights: Vec<f32> = eligible
+        .iter()
+        .map(|&index| weight(remaining[index], lineup, settings))
+        .collect();
+    match WeightedIndex::new(&weights) {
+        Ok(distribution) => eligible[distribution.sample(rng)],
+        // Only zero weights left; those characters are still better than none.
+        Err(_) => eligible[rng.gen_range(0..eligible.len())],
+    }
+}
+
+/// How likely `character` is to be drawn next, relative to the other candidates.
+fn weight(character: &Character, lineup: &[Character], settings: &DrawSettings) -> f32 {
+    let preferred = lineup.iter().any(|other| {
+        other.pair_rules.prefers_with.contains(&character.name)
+            || character.pair_rules.prefers_with.contains(&other.name)
+    });
+
+    settings.difficulty_weight(character.difficulty)
+        * if preferred { PREFERRED_PAIR_BOOST } else { 1.0 }
+}
+
+/// A running estimate of the chance that any one character of each difficulty ends up in
+/// a candidate lineup, before the composition rules are checked. Built up a few candidates
+/// at a time, up to [`INCLUSION_SAMPLES`], so the UI stays responsive.
+pub struct TierEstimate {
+    settings: DrawSettings,
+    pool: Vec<Character>,
+    pub samples: usize,
+    drawn: BTreeMap<Difficulty, usize>,
+}
+
+impl TierEstimate {
+    pub fn new(settings: &DrawSettings, pool: &[Character]) -> Self {
+        Self {
+            settings: settings.clone(),
+            pool: pool.to_vec(),
+            samples: 0,
+            drawn: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the estimate was made for these settings and pool.
+    pub fn is_for(&self, settings: &DrawSettings, pool: &[Character]) -> bool {
+        self.settings == *settings && same_characters(&self.pool, pool)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.samples >= INCLUSION_SAMPLES
+    }
+
+    /// Draws up to `samples` more candidates from the pool the estimate was made for.
+    pub fn sample<R: Rng + ?Sized>(&mut self, samples: usize, rng: &mut R) {
+        let samples = samples.min(INCLUSION_SAMPLES.saturating_sub(self.samples));
+        for _ in 0..samples {
+            for character in candidate(&self.pool, &self.settings, rng) {
+                *self.drawn.entry(character.difficulty).or_default() += 1;
+            }
+        }
+        self.samples += samples;
+    }
+
+    /// The chance for each difficulty in the pool, once any candidates were drawn.
+    pub fn chances(&self) -> BTreeMap<Difficulty, f32> {
+        if self.samples == 0 {
+            return BTreeMap::new();
+        }
+
+        let mut in_pool: BTreeMap<Difficulty, usize> = BTreeMap::new();
+        for character in &self.pool {
+            *in_pool.entry(character.difficulty).or_default() += 1;
+        }
+
+        in_pool
+            .into_iter()
+            .map(|(difficulty, count)| {
+                let drawn = self.drawn.get(&difficulty).copied().unwrap_or(0);
+                (difficulty, drawn as f32 / (count * self.samples) as f32)
+            })
+            .collect()
+    }
+}
+
+/// A running estimate of how likely each character is to end up in a drawn lineup,
+/// built up a few draws at a time.
+#[derive(Clone)]
+pub struct InclusionEstimate {
+    settings: DrawSettings,
+    pool: Vec<Character>,
+    pub draws: usize,
+    pub failures: usize,
+    counts: BTreeMap<String, usize>,
+}
+
+impl InclusionEstimate {
+    pub fn new(settings: &DrawSettings, pool: &[Character]) -> Self {
+        Self {
+            settings: settings.clone(),
+            pool: pool.to_vec(),
+            draws: 0,
+            failures: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the estimate was made for these settings and pool.
+    pub fn is_for(&self, settings: &DrawSettings, pool: &[Character]) -> bool {
+        self.settings == *settings && same_characters(&self.pool, pool)
+    }
+
+    /// Done after [`INCLUSION_DRAWS`] draws, or early once it is clear that nothing can be
+    /// drawn at all.
+    pub fn is_done(&self) -> bool {
+        self.draws >= INCLUSION_DRAWS || (self.failures >= 3 && self.failures == self.draws)
+    }
+
+    /// Draws `draws` more lineups from the pool the estimate was made for.
+    pub fn sample<R: Rng + ?Sized>(&mut self, roster: &Roster, draws: usize, rng: &mut R) {
+        for _ in 0..draws {
+            if self.is_done() {
+                return;
+            }
+
+            self.draws += 1;
+            match draw(&self.pool, roster, &self.settings, rng) {
+                Ok(lineup) => {
+                    for character in lineup {
+                        *self.counts.entry(character.name).or_default() += 1;
+                    }
+                }
+                Err(_) => self.failures += 1,
+            }
+        }
+    }
+
+    /// The share of successful draws that included the character, if any succeeded.
+    pub fn chance(&self, name: &str) -> Option<f32> {
+        let successes = self.draws - self.failures;
+        (successes > 0)
+            .then(|| self.counts.get(name).copied().unwrap_or(0) as f32 / successes as f32)
+    }
+}
+
+/// An [`InclusionEstimate`] built up on a thread of its own, since a single draw may go
+/// through thousands of candidates. Dropping it stops the thread. On the web, where there
+/// are no threads, one draw is added each time the estimate is looked at.
+pub struct PendingInclusion {
+    estimate: InclusionEstimate,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: mpsc::Receiver<InclusionEstimate>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancelled: Arc<AtomicBool>,
+    #[cfg(target_arch = "wasm32")]
+    roster: Roster,
+}
+
+impl PendingInclusion {
+    pub fn start(settings: &DrawSettings, pool: &[Character], roster: &Roster) -> Self {
+        let estimate = InclusionEstimate::new(settings, pool);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let cancelled: Arc<AtomicBool> = Arc::default();
+            let (sender, receiver) = mpsc::channel();
+            let mut running = estimate.clone();
+            let roster = roster.clone();
+            let stop = Arc::clone(&cancelled);
+            std::thread::spawn(move || {
+                let mut rng = rand::thread_rng();
+                while !running.is_done() && !stop.load(Ordering::Relaxed) {
+                    running.sample(&roster, 1, &mut rng);
+                    if sender.send(running.clone()).is_err() {
+                        break;
+                    }
+                }
+            });
+            Self {
+                estimate,
+                receiver,
+                cancelled,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        Self {
+            estimate,
+            roster: roster.clone(),
+        }
+    }
+
+    /// Whether the estimate was made for these settings and pool.
+    pub fn is_for(&self, settings: &DrawSettings, pool: &[Character]) -> bool {
+        self.estimate.is_for(settings, pool)
+    }
+
+    /// The estimate as far as it has got.
+    pub fn estimate(&mut self) -> &InclusionEstimate {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(latest) = self.receiver.try_iter().last() {
+            self.estimate = latest;
+        }
+        #[cfg(target_arch = "wasm32")]
+        self.estimate
+            .sample(&self.roster, 1, &mut rand::thread_rng());
+        &self.estimate
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for PendingInclusion {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::DifficultyQuota;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn veto(character: &Character) -> Veto {
+        Veto {
+            player: "Ann".to_string(),
+            character: character.name.clone(),
+        }
+    }
+
+    #[test]
+    fn replacements_skip_characters_vetoed_earlier_in_the_round() {
+        let roster = Roster::built_in();
+        let pool = roster.characters[..4].to_vec();
+        let settings = DrawSettings::unbounded(2);
+        // The third character was vetoed and replaced by the second; now the first is vetoed.
+        let lineup = vec![pool[0].clone(), pool[1].clone()];
+        let round = [veto(&pool[2]), veto(&pool[0])];
+
+        for seed in 0..20 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let replaced = replace_vetoed(&lineup, &round, &pool, &roster, &settings, &mut rng)
+                .ok()
+                .expect("the fourth character is free");
+            assert!(replaced.contains(&pool[1]));
+            assert!(replaced.contains(&pool[3]));
+        }
+    }
+
+    #[test]
+    fn best_of_keeps_the_highest_scoring_lineup() {
+        let roster = Roster::built_in();
+        let settings = DrawSettings {
+            best_of: 10,
+            ..Default::default()
+        };
+
+        for seed in 0..5 {
+            let drawn = draw(
+                &roster.characters,
+                &roster,
+                &settings,
+                &mut ChaCha8Rng::seed_from_u64(seed),
+            )
+            .ok()
+            .unwrap();
+
+            // The same candidates, drawn one by one from the same seed.
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let candidates: Vec<(f32, Vec<Character>)> = (0..settings.best_of)
+                .map(|_| {
+                    let lineup = draw_valid(&roster.characters, &roster, &settings, &mut rng)
+                        .ok()
+                        .unwrap();
+                    (
+                        score(&lineup, &roster.characters, &roster, &settings).total,
+                        lineup,
+                    )
+                })
+                .collect();
+            let best = candidates
+                .iter()
+                .map(|(score, _)| *score)
+                .fold(f32::MIN, f32::max);
+
+            assert!(candidates.iter().any(|(_, lineup)| *lineup == drawn));
+            assert_eq!(
+                score(&drawn, &roster.characters, &roster, &settings).total,
+                best
+            );
+        }
+    }
+
+    #[test]
+    fn capacity_shortages_blame_quotas_only_when_they_limit() {
+        let pool = Roster::built_in().characters;
+        let mut settings = DrawSettings {
+            number_of_characters: 100,
+            ..Default::default()
+        };
+        let messages = |settings: &DrawSettings| -> Vec<String> {
+            shortages(&pool, settings)
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+
+        assert_eq!(
+            messages(&settings),
+            ["Only 27 characters can be drawn; the lineup would need 73 more."]
+        );
+
+        settings.number_of_characters = 15;
+        settings.difficulty_quotas.insert(
+            Difficulty::Easy,
+            DifficultyQuota {
+                min: 0,
+                max: Some(0),
+                percent: false,
+            },
+        );
+        assert_eq!(
+            messages(&settings),
+            ["The difficulty quotas allow only 14 characters; the lineup would need 1 more."]
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! A small HTTP server that lets every player fetch their own identity on their own device.
+//!
+//! Players open the join page the host serves, enter the room code, pick their seat and
+//! receive only that seat's identity, or its draft pool to choose from.
+//!
+//! * `GET /`: the join page.
+//! * `GET /seats?code=ROOM`: every seat, and whether someone has joined it.
+//! * `POST /join` with `{"code", "player"}`: claims a seat; answers with the seat's
+//!   [`Hand`], including the token that identifies the player from then on.
+//! * `GET /hand?token=TOKEN`: the seat's [`Hand`] again, e.g. after reloading the page.
+//! * `POST /choose` with `{"token", "identity"}`: picks an identity from a draft pool.
+
+use crate::fairness::to_hex;
+use crate::http::{self, error, json, parameter, Reply};
+use crate::identity::Deal;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tiny_http::{Method, Request, Server};
+
+const JOIN_PAGE: &str = include_str!("../assets/join.html");
+
+/// Letters that can't be mistaken for one another when read out loud or off a screen.
+const CODE_LETTERS: &[u8] = b"ACDEFHJKLMNPQRTUVWXY";
+const CODE_LENGTH: usize = 4;
+
+/// Whether someone has joined a seat, as the host sees it.
+#[derive(serde::Serialize, Clone)]
+pub struct SeatStatus {
+    pub player: String,
+    pub joined: bool,
+    pub chosen: bool,
+}
+
+/// What a player may see of the deal: their own seat, and nothing else.
+#[derive(serde::Serialize)]
+struct Hand {
+    token: String,
+    player: String,
+    options: Vec<String>,
+    identity: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct JoinRequest {
+    code: String,
+    player: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ChooseRequest {
+    token: String,
+    identity: String,
+}
+
+struct RoomState {
+    code: String,
+    deal: Deal,
+    /// The token of the player who joined each seat.
+    tokens: Vec<Option<String>>,
+}
+
+impl RoomState {
+    fn hand(&self, seat: usize) -> Hand {
+        let dealt = &self.deal.seats[seat];
+        Hand {
+            token: self.tokens[seat].clone().unwrap_or_default(),
+            player: dealt.player.clone(),
+            options: dealt.options.clone(),
+            identity: dealt.identity.clone(),
+        }
+    }
+
+    fn seat_of(&self, token: &str) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|seat| seat.as_deref() == Some(token))
+    }
+}
+
+/// A running room. The server stops when the room is dropped.
+pub struct Room {
+    state: Arc<Mutex<RoomState>>,
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+    address: SocketAddr,
+}
+
+impl Room {
+    /// Opens a room for `deal` on `address`, e.g. `0.0.0.0:7878` to be reachable over
+    /// the LAN, or `127.0.0.1:0` for any free port on this machine only.
+    pub fn open(address: &str, deal: Deal) -> io::Result<Self> {
+        let server =
+            Server::http(address).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        let address = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "not an IP address"))?;
+        let server = Arc::new(server);
+
+        let mut rng = thread_rng();
+        let code = (0..CODE_LENGTH)
+            .map(|_| *CODE_LETTERS.choose(&mut rng).unwrap() as char)
+            .collect();
+        let state = Arc::new(Mutex::new(RoomState {
+            code,
+            tokens: vec![None; deal.seats.len()],
+            deal,
+        }));
+
+        let thread = {
+            let server = server.clone();
+            let state = state.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &state);
+                }
+            })
+        };
+
+        Ok(Self {
+            state,
+            server,
+            thread: Some(thread),
+            address,
+        })
+    }
+
+    pub fn code(&self) -> String {
+        self.state.lock().unwrap().code.clone()
+    }
+
+    /// The address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The address players on the LAN can reach the server at, when it can be found.
+    pub fn lan_url(&self) -> Option<String> {
+        // Connecting a UDP socket sends nothing, but picks the interface a packet would
+        // leave through.
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("192.0.2.1:80").ok()?;
+        let ip = socket.local_addr().ok()?.ip();
+        Some(format!("http://{}:{}", ip, self.address.port()))
+    }
+
+    /// The deal, with the identities players have chosen so far.
+    pub fn deal(&self) -> Deal {
+        self.state.lock().unwrap().deal.clone()
+    }
+
+    pub fn status(&self) -> Vec<SeatStatus> {
+        let state = self.state.lock().unwrap();
+        status(&state)
+    }
+}
+
+impl Drop for Room {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn status(state: &RoomState) -> Vec<SeatStatus> {
+    state
+        .deal
+        .seats
+        .iter()
+        .zip(&state.tokens)
+        .map(|(seat, token)| SeatStatus {
+            player: seat.player.clone(),
+            joined: token.is_some(),
+            chosen: seat.identity.is_some(),
+        })
+        .collect()
+}
+
+fn handle(mut request: Request, state: &Mutex<RoomState>) {
+    let response = match http::read(&mut request) {
+        Err(reply) => reply,
+        Ok(incoming) => route(request.method(), &incoming, &mut state.lock().unwrap()),
+    };
+    let _ = request.respond(response);
+}
+
+fn route(method: &Method, incoming: &http::Incoming, state: &mut RoomState) -> Reply {
+    let http::Incoming { path, query, body } = incoming;
+    match (method, path.as_str()) {
+        (Method::Get, "/") => http::html(JOIN_PAGE),
+        (Method::Get, "/seats") => {
+            if parameter(query, "code").map_or(true, |code| !code.eq_ignore_ascii_case(&state.code))
+            {
+                return error(404, "There is no room with that code.");
+            }
+            json(200, &status(state))
+        }
+        (Method::Post, "/join") => {
+            let Ok(join) = serde_json::from_str::<JoinRequest>(body) else {
+                return error(400, "Expected a code and a player.");
+            };
+            if !join.code.trim().eq_ignore_ascii_case(&state.code) {
+                return error(404, "There is no room with that code.");
+            }
+            let Some(seat) = state
+                .deal
+                .seats
+                .iter()
+                .position(|seat| seat.player == join.player)
+            else {
+                return error(404, "There is no such player in this room.");
+            };
+            if state.tokens[seat].is_some() {
+                return error(409, "Someone has already joined as this player.");
+            }
+            state.tokens[seat] = Some(to_hex(&thread_rng().gen::<[u8; 16]>()));
+            json(200, &state.hand(seat))
+        }
+        (Method::Get, "/hand") => {
+            match parameter(query, "token").and_then(|token| state.seat_of(token)) {
+                Some(seat) => json(200, &state.hand(seat)),
+                None => error(403, "Unknown token."),
+            }
+        }
+        (Method::Post, "/choose") => {
+            let Ok(choose) = serde_json::from_str::<ChooseRequest>(body) else {
+                return error(400, "Expected a token and an identity.");
+            };
+            let Some(seat) = state.seat_of(&choose.token) else {
+                return error(403, "Unknown token.");
+            };
+            if state.deal.seats[seat].identity.is_some() {
+                return error(409, "You have already chosen.");
+            }
+            if !state.deal.seats[seat].options.contains(&choose.identity) {
+                return error(400, "That identity isn't one of your options.");
+            }
+            state.deal.choose(seat, &choose.identity);
+            json(200, &state.hand(seat))
+        }
+        _ => error(404, "Not found."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Deal;
+    use crate::roster::Roster;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    /// Sends a bare HTTP/1.0 request and returns the status code and body.
+    fn send(room: &Room, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(room.address()).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap_or_default())
+    }
+
+    fn open(deal: Deal) -> Room {
+        Room::open("127.0.0.1:0", deal).unwrap()
+    }
+
+    #[test]
+    fn players_only_see_their_own_identity() {
+        let roster = Roster::built_in();
+        let deal = Deal::random(&roster.characters[..12], 3, &mut thread_rng())
+            .ok()
+            .unwrap();
+        let room = open(deal.clone());
+        let code = room.code();
+
+        let (status, seats) = send(&room, "GET", &format!("/seats?code={code}"), "");
+        assert_eq!(status, 200);
+        assert_eq!(seats.as_array().unwrap().len(), 3);
+
+        let (status, hand) = send(
+            &room,
+            "POST",
+            "/join",
+            &format!(r#"{{"code": "{code}", "player": "Player 2"}}"#),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(
+            hand["identity"],
+            deal.seats[1].identity.clone().unwrap().as_str()
+        );
+        assert_eq!(hand["options"].as_array().unwrap().len(), 1);
+
+        let (status, _) = send(
+            &room,
+            "POST",
+            "/join",
+            &format!(r#"{{"code": "{code}", "player": "Player 2"}}"#),
+        );
+        assert_eq!(status, 409);
+
+        let token = hand["token"].as_str().unwrap();
+        let (status, again) = send(&room, "GET", &format!("/hand?token={token}"), "");
+        assert_eq!(status, 200);
+        assert_eq!(again["player"], "Player 2");
+
+        let joined: Vec<bool> = room.status().iter().map(|seat| seat.joined).collect();
+        assert_eq!(joined, [false, true, false]);
+    }
+
+    #[test]
+    fn rejects_wrong_codes_and_tokens() {
+        let roster = Roster::built_in();
+        let deal = Deal::random(&roster.characters[..12], 2, &mut thread_rng())
+            .ok()
+            .unwrap();
+        let room = open(deal);
+
+        assert_eq!(send(&room, "GET", "/seats?code=ZZZZZ", "").0, 404);
+        assert_eq!(
+            send(
+                &room,
+                "POST",
+                "/join",
+                r#"{"code": "ZZZZZ", "player": "Player 1"}"#
+            )
+            .0,
+            404
+        );
+        assert_eq!(send(&room, "GET", "/hand?token=nope", "").0, 403);
+    }
+
+    #[test]
+    fn players_choose_from_their_draft_pool() {
+        let roster = Roster::built_in();
+        let deal = Deal::draft(&roster.characters[..12], 4, &mut thread_rng())
+            .ok()
+            .unwrap();
+        let room = open(deal);
+        let code = room.code();
+
+        let (_, hand) = send(
+            &room,
+            "POST",
+            "/join",
+            &format!(r#"{{"code": "{code}", "player": "Player 1"}}"#),
+        );
+        assert!(hand["identity"].is_null());
+        let token = hand["token"].as_str().unwrap();
+        let option = hand["options"][0].as_str().unwrap();
+
+        let (status, _) = send(
+            &room,
+            "POST",
+            "/choose",
+            &format!(r#"{{"token": "{token}", "identity": "Nobody"}}"#),
+        );
+        assert_eq!(status, 400);
+
+        let (status, chosen) = send(
+            &room,
+            "POST",
+            "/choose",
+            &format!(r#"{{"token": "{token}", "identity": "{option}"}}"#),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(chosen["identity"], option);
+        assert_eq!(room.deal().seats[0].identity.as_deref(), Some(option));
+        assert!(room.status()[0].chosen);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::character::Character;
+use std::collections::BTreeMap;
+
+/// Whether a tag describes what a character is or what it is after.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy)]
+pub enum TagKind {
+    Trait,
+    Goal,
+}
+
+/// A trait or goal that characters can be tagged with.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct TagDefinition {
+    pub id: String,
+    pub label: String,
+    pub icon: String,
+    pub kind: TagKind,
+}
+
+/// The characters that can be drawn, and the tags they use.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Roster {
+    pub tags: Vec<TagDefinition>,
+    pub characters: Vec<Character>,
+}
+
+impl Roster {
+    /// The roster that ships with the app, read from `assets/roster.ron`.
+    pub fn built_in() -> Self {
+        ron::from_str(include_str!("../assets/roster.ron")).expect("built-in roster is valid")
+    }
+
+    /// How many characters in `lineup` have each tag. Every tag is present, even at zero.
+    pub fn tag_counts(&self, lineup: &[Character]) -> BTreeMap<String, usize> {
+        self.tags
+            .iter()
+            .map(|tag| {
+                let count = lineup
+                    .iter()
+                    .filter(|character| character.has_tag(&tag.id))
+                    .count();
+                (tag.id.clone(), count)
+            })
+            .collect()
+    }
+
+    /// Checks characters added to the roster: each needs a name of its own, aggression
+    /// between 0 and 1 and only tags the roster defines. The error says what is wrong.
+    pub fn check_custom_characters(&self, characters: &[Character]) -> Result<(), String> {
+        for (index, character) in characters.iter().enumerate() {
+            if character.name.trim().is_empty() {
+                return Err("a custom character has no name".to_string());
+            }
+            if characters[..index].contains(character) {
+                return Err(format!("{} is listed more than once", character.name));
+            }
+            if !(0.0..=1.0).contains(&character.aggression) {
+                return Err(format!(
+                    "{} has aggression {} (must be between 0 and 1)",
+                    character.name, character.aggression
+                ));
+            }
+            if let Some(tag) = character
+                .tags
+                .iter()
+                .find(|tag| !self.tags.iter().any(|known| known.id == **tag))
+            {
+                return Err(format!(
+                    "{} has the unknown tag \"{}\"",
+                    character.name, tag
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn goals(&self) -> impl Iterator<Item = &TagDefinition> {
+        self.tags.iter().filter(|tag| tag.kind == TagKind::Goal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_relationships_name_roster_characters() {
+        let roster = Roster::built_in();
+        let known = |name: &String| roster.characters.iter().any(|other| other.name == *name);
+        let mut relationships = 0;
+        for character in &roster.characters {
+            for (_, name) in character.relationships.iter() {
+                assert!(
+                    known(name),
+                    "{} relates to unknown {}",
+                    character.name,
+                    name
+                );
+                relationships += 1;
+            }
+            for (_, name) in character.pair_rules.iter() {
+                assert!(
+                    known(name),
+                    "{} has a rule for unknown {}",
+                    character.name,
+                    name
+                );
+            }
+        }
+        assert!(relationships > 0);
+    }
+}
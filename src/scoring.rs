@@ -0,0 +1,238 @@
+use crate::character::{Character, Difficulty};
+use crate::constraints::{average_aggression, goal_ambiguity};
+use crate::roster::Roster;
+use crate::settings::DrawSettings;
+
+/// One part of the balance score.
+#[derive(serde::Serialize)]
+pub struct ScorePart {
+    pub name: &'static str,
+    /// From 0 (worst) to 1 (best).
+    pub score: f32,
+    pub weight: f32,
+}
+
+#[derive(serde::Serialize)]
+pub struct Score {
+    pub parts: Vec<ScorePart>,
+    /// The weighted average of the parts, from 0 to 1.
+    pub total: f32,
+}
+
+/// Rates how balanced `lineup` is, drawn from `pool` with `settings`:
+///
+/// * aggression: how close the average is to the middle of the wanted range,
+/// * ambiguity: the [`goal_ambiguity`],
+/// * difficulty mix: how close the share of each difficulty is to what the difficulty
+///   weights ask for,
+/// * trait diversity: how evenly the lineup is spread over all tags.
+pub fn score(
+    lineup: &[Character],
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+) -> Score {
+    let weights = &settings.score_weights;
+    let parts = vec![
+        ScorePart {
+            name: "Aggression",
+            score: aggression_closeness(lineup, settings),
+            weight: weights.aggression,
+        },
+        ScorePart {
+            name: "Goal ambiguity",
+            score: goal_ambiguity(lineup, roster).unwrap_or(0.0),
+            weight: weights.ambiguity,
+        },
+        ScorePart {
+            name: "Difficulty mix",
+            score: difficulty_mix(lineup, pool, settings),
+            weight: weights.difficulty_mix,
+        },
+        ScorePart {
+            name: "Trait diversity",
+            score: evenness(roster.tag_counts(lineup).into_values()),
+            weight: weights.trait_diversity,
+        },
+    ];
+
+    let total_weight: f32 = parts.iter().map(|part| part.weight).sum();
+    let total = if total_weight > 0.0 {
+        parts
+            .iter()
+            .map(|part| part.score * part.weight)
+            .sum::<f32>()
+            / total_weight
+    } else {
+        0.0
+    };
+
+    Score { parts, total }
+}
+
+fn aggression_closeness(lineup: &[Character], settings: &DrawSettings) -> f32 {
+    let range = settings.aggression_range;
+    let target = (range.min + range.max) / 2.0;
+    // Divided by the furthest any average can be from the target.
+    1.0 - (average_aggression(lineup) - target).abs() / target.max(1.0 - target)
+}
+
+/// One minus the total variation distance between the lineup's share of each difficulty
+/// and the pool's, with each difficulty scaled by its weight.
+fn difficulty_mix(lineup: &[Character], pool: &[Character], settings: &DrawSettings) -> f32 {
+    let share = |characters: &[Character], weight: &dyn Fn(Difficulty) -> f32| {
+        let weighted = |difficulty| {
+            characters
+                .iter()
+                .filter(|character| character.difficulty == difficulty)
+                .count() as f32
+                * weight(difficulty)
+        };
+        let total: f32 = Difficulty::ALL.into_iter().map(weighted).sum();
+        Difficulty::ALL.map(|difficulty| {
+            if total > 0.0 {
+                weighted(difficulty) / total
+            } else {
+                0.0
+            }
+        })
+    };
+
+    let wanted = share(pool, &|difficulty| settings.difficulty_weight(difficulty));
+    let actual = share(lineup, &|_| 1.0);
+    let distance: f32 = wanted
+        .iter()
+        .zip(actual)
+        .map(|(wanted, actual)| (wanted - actual).abs())
+        .sum::<f32>()
+        / 2.0;
+    1.0 - distance
+}
+
+/// The Shannon entropy of `counts`, relative to the most even spread possible.
+fn evenness(counts: impl Iterator<Item = usize>) -> f32 {
+    let counts: Vec<usize> = counts.collect();
+    let total: usize = counts.iter().sum();
+    if total == 0 || counts.len() < 2 {
+        return 0.0;
+    }
+
+    let entropy: f32 = counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f32 / total as f32;
+            -p * p.ln()
+        })
+        .sum();
+    entropy / (counts.len() as f32).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{AggressionRange, ScoreWeights};
+    use std::collections::BTreeMap;
+
+    fn character(difficulty: Difficulty, aggression: f32) -> Character {
+        Character {
+            name: format!("{} {aggression}", difficulty.name()),
+            difficulty,
+            aggression,
+            tags: Default::default(),
+            pair_rules: Default::default(),
+            relationships: Default::default(),
+        }
+    }
+
+    fn even_weights() -> DrawSettings {
+        DrawSettings {
+            difficulty_weights: Some(Difficulty::ALL.map(|difficulty| (difficulty, 1.0)).into()),
+            ..Default::default()
+        }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn aggression_closeness_measures_distance_from_the_middle_of_the_range() {
+        let settings = DrawSettings::default();
+        let lineup = |aggression| [character(Difficulty::Easy, aggression)];
+
+        assert_near(aggression_closeness(&lineup(0.5), &settings), 1.0);
+        assert_near(aggression_closeness(&lineup(0.25), &settings), 0.5);
+        assert_near(aggression_closeness(&lineup(1.0), &settings), 0.0);
+
+        // Aiming for 0.215, the furthest an average can be is 0.785 away.
+        let peaceful = DrawSettings {
+            aggression_range: AggressionRange {
+                min: 0.0,
+                max: 0.43,
+            },
+            ..Default::default()
+        };
+        assert_near(
+            aggression_closeness(&lineup(0.0), &peaceful),
+            1.0 - 0.215 / 0.785,
+        );
+    }
+
+    #[test]
+    fn difficulty_mix_compares_the_lineup_with_the_weighted_pool() {
+        let easy = character(Difficulty::Easy, 0.0);
+        let hard = character(Difficulty::Hard, 0.0);
+        let pool = [easy.clone(), easy.clone(), hard.clone(), hard.clone()];
+        let mut settings = even_weights();
+
+        assert_near(
+            difficulty_mix(&[easy.clone(), hard.clone()], &pool, &settings),
+            1.0,
+        );
+        assert_near(
+            difficulty_mix(&[easy.clone(), easy.clone()], &pool, &settings),
+            0.5,
+        );
+
+        // Hard characters weigh three times as much: the pool asks for 1/4 easy, 3/4 hard.
+        settings.difficulty_weights = Some(BTreeMap::from([
+            (Difficulty::Easy, 1.0),
+            (Difficulty::Hard, 3.0),
+        ]));
+        assert_near(difficulty_mix(&[easy, hard], &pool, &settings), 0.75);
+    }
+
+    #[test]
+    fn evenness_is_relative_entropy() {
+        assert_near(evenness([2, 2].into_iter()), 1.0);
+        assert_near(evenness([4, 0].into_iter()), 0.0);
+        assert_near(evenness([0, 0].into_iter()), 0.0);
+        assert_near(evenness([3].into_iter()), 0.0);
+        // -(2 · ¼ ln ¼ + ½ ln ½) / ln 3
+        assert_near(evenness([1, 1, 2].into_iter()), 0.9464);
+    }
+
+    #[test]
+    fn total_is_the_weighted_average_of_the_parts() {
+        let roster = Roster::built_in();
+        let easy = character(Difficulty::Easy, 0.0);
+        let hard = character(Difficulty::Hard, 0.0);
+        let pool = [easy.clone(), easy.clone(), hard.clone(), hard];
+        let mut settings = even_weights();
+        settings.score_weights = ScoreWeights {
+            aggression: 1.0,
+            ambiguity: 0.0,
+            difficulty_mix: 3.0,
+            trait_diversity: 0.0,
+        };
+
+        // Aggression scores 0 and the difficulty mix 0.5.
+        let lineup = [easy.clone(), easy];
+        assert_near(score(&lineup, &pool, &roster, &settings).total, 0.375);
+
+        settings.score_weights.aggression = 0.0;
+        settings.score_weights.difficulty_mix = 0.0;
+        assert_near(score(&lineup, &pool, &roster, &settings).total, 0.0);
+    }
+}
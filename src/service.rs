@@ -0,0 +1,213 @@
+//! Drawing and checking lineups for callers outside the GUI, with requests and answers in
+//! the layouts documented in `docs/api.md`.
+
+use crate::character::Character;
+use crate::constraints::{average_aggression, check_lineup, goal_ambiguity, Check};
+use crate::counting::count_lineups;
+use crate::randomizer;
+use crate::roster::Roster;
+use crate::scoring::{score, Score};
+use crate::settings::{DrawSettings, MAX_ALTERNATIVES, MAX_BEST_OF};
+use rand::{thread_rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::BTreeMap;
+
+/// What every request that draws from or checks against the roster may ask for.
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+pub struct Query {
+    /// The same settings as the side panel; left out, the defaults.
+    pub settings: DrawSettings,
+    /// Characters added to the built-in roster.
+    pub custom_characters: Vec<Character>,
+    /// Draws the same lineup for the same seed, settings and characters.
+    pub seed: Option<u64>,
+    /// The lineup to check, by name.
+    pub lineup: Vec<String>,
+}
+
+/// How a lineup measures up to the settings.
+#[derive(serde::Serialize)]
+pub struct LineupReport {
+    pub lineup: Vec<String>,
+    pub valid: bool,
+    pub checks: Vec<Check>,
+    pub average_aggression: f32,
+    pub goal_ambiguity: Option<f32>,
+    pub score: Score,
+}
+
+#[derive(serde::Serialize)]
+pub struct FailureReport {
+    pub error: &'static str,
+    pub shortages: Vec<String>,
+    pub rejections: BTreeMap<String, usize>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Feasibility {
+    /// `None` when the pair rules are too tangled to count.
+    pub lineups: Option<u128>,
+    pub blocking: Vec<String>,
+    pub shortages: Vec<String>,
+}
+
+pub enum ServiceError {
+    /// The request can't be answered as asked.
+    BadRequest(String),
+    /// No lineup passes every rule.
+    NoLineup(FailureReport),
+}
+
+/// Reads a [`Query`] from JSON. An empty body is the same as `{}`.
+pub fn parse_query(json: &str) -> Result<Query, ServiceError> {
+    if json.trim().is_empty() {
+        return Ok(Query::default());
+    }
+    serde_json::from_str(json)
+        .map_err(|error| ServiceError::BadRequest(format!("Could not read the request: {error}")))
+}
+
+pub fn draw(query: &Query, roster: &Roster) -> Result<LineupReport, ServiceError> {
+    let pool = pool(query, roster)?;
+    let drawn = match query.seed {
+        Some(seed) => randomizer::draw(
+            &pool,
+            roster,
+            &query.settings,
+            &mut ChaCha20Rng::seed_from_u64(seed),
+        ),
+        None => randomizer::draw(&pool, roster, &query.settings, &mut thread_rng()),
+    };
+    match drawn {
+        Ok(lineup) => Ok(report(lineup, &pool, roster, &query.settings)),
+        Err(failure) => Err(ServiceError::NoLineup(FailureReport {
+            error: "No lineup passes every rule.",
+            shortages: failure.shortages.iter().map(ToString::to_string).collect(),
+            rejections: failure.rejections,
+        })),
+    }
+}
+
+/// Checks the query's lineup against its settings.
+pub fn validate(query: &Query, roster: &Roster) -> Result<LineupReport, ServiceError> {
+    let pool = pool(query, roster)?;
+    let mut lineup = vec![];
+    let mut unknown = vec![];
+    for name in &query.lineup {
+        match pool.iter().find(|character| character.name == *name) {
+            Some(character) => lineup.push(character.clone()),
+            None => unknown.push(name.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown characters: {}",
+            unknown.join(", ")
+        )));
+    }
+    let mut repeated: Vec<&str> = vec![];
+    for (index, character) in lineup.iter().enumerate() {
+        if lineup[..index].contains(character) && !repeated.contains(&character.name.as_str()) {
+            repeated.push(&character.name);
+        }
+    }
+    if !repeated.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Characters listed more than once: {}",
+            repeated.join(", ")
+        )));
+    }
+    Ok(report(lineup, &pool, roster, &query.settings))
+}
+
+pub fn feasibility(query: &Query, roster: &Roster) -> Result<Feasibility, ServiceError> {
+    let pool = pool(query, roster)?;
+    let count = count_lineups(&pool, roster, &query.settings);
+    Ok(Feasibility {
+        lineups: count.lineups,
+        blocking: count.blocking,
+        shortages: randomizer::shortages(&pool, &query.settings)
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// The built-in characters and the query's custom ones.
+fn pool(query: &Query, roster: &Roster) -> Result<Vec<Character>, ServiceError> {
+    if query.settings.number_of_characters == 0 {
+        return Err(ServiceError::BadRequest(
+            "number_of_characters must be at least 1".to_string(),
+        ));
+    }
+    if !(1..=MAX_BEST_OF).contains(&query.settings.best_of) {
+        return Err(ServiceError::BadRequest(format!(
+            "best_of must be between 1 and {MAX_BEST_OF}"
+        )));
+    }
+    if !(1..=MAX_ALTERNATIVES).contains(&query.settings.alternatives) {
+        return Err(ServiceError::BadRequest(format!(
+            "alternatives must be between 1 and {MAX_ALTERNATIVES}"
+        )));
+    }
+    let size = query.settings.number_of_characters;
+    for (difficulty, quota) in &query.settings.difficulty_quotas {
+        let limit = if quota.percent { 100 } else { size };
+        if quota.min > limit || quota.max.map_or(false, |max| max > limit) {
+            return Err(ServiceError::BadRequest(format!(
+                "The {} quota must stay within {}{}",
+                difficulty.name(),
+                limit,
+                if quota.percent { "%" } else { " characters" }
+            )));
+        }
+    }
+    roster
+        .check_custom_characters(&query.custom_characters)
+        .map_err(|reason| {
+            ServiceError::BadRequest(format!("Invalid custom character: {reason}"))
+        })?;
+    if let Some(character) = query
+        .custom_characters
+        .iter()
+        .find(|character| roster.characters.contains(character))
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "Custom character \"{}\" has the same name as a built-in character.",
+            character.name
+        )));
+    }
+
+    let pool: Vec<Character> = roster
+        .characters
+        .iter()
+        .chain(&query.custom_characters)
+        .cloned()
+        .collect();
+    if size > pool.len() {
+        return Err(ServiceError::BadRequest(format!(
+            "number_of_characters must be at most {}, the number of characters",
+            pool.len()
+        )));
+    }
+    Ok(pool)
+}
+
+fn report(
+    lineup: Vec<Character>,
+    pool: &[Character],
+    roster: &Roster,
+    settings: &DrawSettings,
+) -> LineupReport {
+    let checks = check_lineup(&lineup, roster, settings);
+    LineupReport {
+        valid: lineup.len() == settings.number_of_characters
+            && checks.iter().all(|check| check.passed),
+        average_aggression: average_aggression(&lineup),
+        goal_ambiguity: goal_ambiguity(&lineup, roster),
+        score: score(&lineup, pool, roster, settings),
+        lineup: lineup.into_iter().map(|character| character.name).collect(),
+        checks,
+    }
+}
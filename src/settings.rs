@@ -0,0 +1,364 @@
+use crate::character::{AggressionLevel, Character, Difficulty};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The average aggression that separates peaceful from aggressive lineups.
+pub const AGGRESSION_THRESHOLD: f32 = 0.43;
+
+/// The most lineups [`DrawSettings::best_of`] may ask to compare.
+pub const MAX_BEST_OF: usize = 100;
+
+/// The most lineups [`DrawSettings::alternatives`] may ask to offer.
+pub const MAX_ALTERNATIVES: usize = 5;
+
+/// The range a lineup's average aggression has to fall in.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy)]
+pub struct AggressionRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl AggressionRange {
+    pub const ANY: Self = Self { min: 0.0, max: 1.0 };
+
+    pub fn contains(&self, average: f32) -> bool {
+        self.min <= average && average <= self.max
+    }
+}
+
+impl From<AggressionLevel> for AggressionRange {
+    fn from(level: AggressionLevel) -> Self {
+        match level {
+            AggressionLevel::Peaceful => Self {
+                min: 0.0,
+                max: AGGRESSION_THRESHOLD,
+            },
+            AggressionLevel::Random => Self::ANY,
+            AggressionLevel::Aggressive => Self {
+                min: AGGRESSION_THRESHOLD,
+                max: 1.0,
+            },
+        }
+    }
+}
+
+/// The rules the side panel offers for goals, each combined with a number of characters.
+#[derive(PartialEq, Clone, Copy)]
+pub enum GoalRule {
+    Any,
+    /// The goal must be in play, with at least the given number of characters.
+    Required,
+    /// The goal may be left out, but if it is in play it needs at least that many.
+    NoneOrAtLeast,
+}
+
+/// How many characters with a given tag a lineup may contain.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct TagBound {
+    pub min: usize,
+    pub max: Option<usize>,
+    /// Also accept lineups without any character with the tag, even if `min` is higher.
+    pub or_none: bool,
+}
+
+impl TagBound {
+    pub fn at_least(min: usize) -> Self {
+        Self {
+            min,
+            ..Default::default()
+        }
+    }
+
+    pub fn none_or_at_least(min: usize) -> Self {
+        Self {
+            min,
+            max: None,
+            or_none: true,
+        }
+    }
+
+    pub fn goal_rule(&self) -> GoalRule {
+        match (self.min, self.or_none) {
+            (0, _) => GoalRule::Any,
+            (_, false) => GoalRule::Required,
+            (_, true) => GoalRule::NoneOrAtLeast,
+        }
+    }
+
+    /// The bound set to `rule` with `min` characters. A goal rule says nothing about the
+    /// maximum, so the bound's own is kept.
+    pub fn with_goal_rule(&self, rule: GoalRule, min: usize) -> Self {
+        let (min, or_none) = match rule {
+            GoalRule::Any => (0, false),
+            GoalRule::Required => (min.max(1), false),
+            GoalRule::NoneOrAtLeast => (min.max(1), true),
+        };
+        Self {
+            min,
+            max: self.max,
+            or_none,
+        }
+    }
+
+    pub fn allows(&self, count: usize) -> bool {
+        (self.or_none && count == 0)
+            || (count >= self.min && self.max.map_or(true, |max| count <= max))
+    }
+
+    /// A short human readable form, e.g. "2–4" or "0 or at least 3".
+    pub fn describe(&self) -> String {
+        let range = match (self.min, self.max) {
+            (0, None) => "any number".to_string(),
+            (min, None) => format!("at least {}", min),
+            (0, Some(max)) => format!("at most {}", max),
+            (min, Some(max)) if min == max => format!("exactly {}", min),
+            (min, Some(max)) => format!("{}–{}", min, max),
+        };
+
+        if self.or_none && self.min > 0 {
+            format!("0 or {}", range)
+        } else {
+            range
+        }
+    }
+}
+
+/// The sampling weight of `difficulty` when `maximum` is the preferred maximum
+/// difficulty. Tiers up to the maximum are favoured the way the old ordered shuffle
+/// favoured them, just not absolutely.
+fn default_difficulty_weight(maximum: Difficulty, difficulty: Difficulty) -> f32 {
+    match (maximum, difficulty) {
+        (Difficulty::Easy, Difficulty::Easy) => 9.0,
+        (Difficulty::Easy, Difficulty::Medium) => 3.0,
+        (Difficulty::Medium, Difficulty::Easy | Difficulty::Medium) => 3.0,
+        (Difficulty::Hard, _) => 1.0,
+        (_, Difficulty::Hard) => 1.0,
+    }
+}
+
+/// How many characters of one difficulty a lineup may contain.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct DifficultyQuota {
+    pub min: usize,
+    pub max: Option<usize>,
+    /// `min` and `max` are percentages of the lineup size rather than character counts.
+    pub percent: bool,
+}
+
+impl DifficultyQuota {
+    /// The quota as character counts for a lineup of `lineup_size`. Percentages round
+    /// outwards, so a 50/30/20 mix stays satisfiable for any size.
+    pub fn bound(&self, lineup_size: usize) -> TagBound {
+        if !self.percent {
+            return TagBound {
+                min: self.min,
+                max: self.max,
+                or_none: false,
+            };
+        }
+
+        TagBound {
+            min: self.min.saturating_mul(lineup_size) / 100,
+            max: self
+                .max
+                .map(|max| max.saturating_mul(lineup_size).saturating_add(99) / 100),
+            or_none: false,
+        }
+    }
+}
+
+/// Constraints on the relationships between drawn characters.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum RelationshipRule {
+    /// Every target of every drawn character is drawn as well.
+    EveryTargetDrawn,
+    /// Every drawn character with targets has at least one of them in the lineup.
+    NoOrphanedHunters,
+}
+
+impl RelationshipRule {
+    pub const ALL: [RelationshipRule; 2] = [
+        RelationshipRule::EveryTargetDrawn,
+        RelationshipRule::NoOrphanedHunters,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelationshipRule::EveryTargetDrawn => "Every target drawn",
+            RelationshipRule::NoOrphanedHunters => "No orphaned hunters",
+        }
+    }
+
+    /// Whether `hunter` may be in a lineup that contains exactly the characters
+    /// `in_lineup` says it does.
+    pub fn allows(&self, hunter: &Character, in_lineup: impl Fn(&str) -> bool) -> bool {
+        let targets = &hunter.relationships.targets;
+        match self {
+            RelationshipRule::EveryTargetDrawn => targets.iter().all(|name| in_lineup(name)),
+            RelationshipRule::NoOrphanedHunters => {
+                targets.is_empty() || targets.iter().any(|name| in_lineup(name))
+            }
+        }
+    }
+}
+
+/// How much each part of the balance score counts towards the total.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct ScoreWeights {
+    pub aggression: f32,
+    pub ambiguity: f32,
+    pub difficulty_mix: f32,
+    pub trait_diversity: f32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            aggression: 1.0,
+            ambiguity: 1.0,
+            difficulty_mix: 1.0,
+            trait_diversity: 1.0,
+        }
+    }
+}
+
+/// Everything that controls how a lineup is drawn.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct DrawSettings {
+    pub number_of_characters: usize,
+    /// Bounds on how many characters have each tag, keyed by tag id. Tags without an
+    /// entry are unconstrained.
+    pub tag_bounds: BTreeMap<String, TagBound>,
+    pub preferred_maximum_difficulty: Difficulty,
+    /// Never draw characters above `preferred_maximum_difficulty`, even if that leaves
+    /// the lineup short.
+    pub strict_maximum_difficulty: bool,
+    /// Relative chance of drawing a character of each difficulty. `None` uses the
+    /// defaults for `preferred_maximum_difficulty`.
+    pub difficulty_weights: Option<BTreeMap<Difficulty, f32>>,
+    /// Quotas per difficulty. Difficulties without an entry are unconstrained.
+    pub difficulty_quotas: BTreeMap<Difficulty, DifficultyQuota>,
+    pub aggression_range: AggressionRange,
+    pub relationship_rules: BTreeSet<RelationshipRule>,
+    pub score_weights: ScoreWeights,
+    /// How many valid lineups to draw; the one with the best balance score is kept.
+    pub best_of: usize,
+    /// How many different lineups to offer side by side.
+    pub alternatives: usize,
+    /// Make the alternatives share as few characters as possible.
+    pub minimize_overlap: bool,
+}
+
+impl DrawSettings {
+    pub fn tag_bound(&self, id: &str) -> TagBound {
+        self.tag_bounds.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn difficulty_weight(&self, difficulty: Difficulty) -> f32 {
+        match &self.difficulty_weights {
+            Some(weights) => weights.get(&difficulty).copied().unwrap_or(1.0),
+            None => default_difficulty_weight(self.preferred_maximum_difficulty, difficulty),
+        }
+    }
+
+    pub fn difficulty_quota(&self, difficulty: Difficulty) -> DifficultyQuota {
+        self.difficulty_quotas
+            .get(&difficulty)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// How many characters of `difficulty` a lineup may contain, taking strict mode
+    /// into account.
+    pub fn difficulty_bound(&self, difficulty: Difficulty) -> TagBound {
+        let mut bound = self
+            .difficulty_quota(difficulty)
+            .bound(self.number_of_characters);
+        if self.strict_maximum_difficulty && difficulty > self.preferred_maximum_difficulty {
+            bound.max = Some(0);
+        }
+        bound
+    }
+}
+
+impl Default for DrawSettings {
+    fn default() -> Self {
+        Self {
+            number_of_characters: 15,
+            tag_bounds: BTreeMap::from([
+                ("officer".to_string(), TagBound::at_least(1)),
+                ("robot".to_string(), TagBound::at_least(1)),
+                ("artifact".to_string(), TagBound::none_or_at_least(2)),
+                ("briefcase".to_string(), TagBound::none_or_at_least(2)),
+                ("contamination".to_string(), TagBound::none_or_at_least(2)),
+            ]),
+            preferred_maximum_difficulty: Difficulty::Hard,
+            strict_maximum_difficulty: false,
+            difficulty_weights: None,
+            difficulty_quotas: BTreeMap::new(),
+            aggression_range: AggressionRange::ANY,
+            relationship_rules: BTreeSet::new(),
+            score_weights: ScoreWeights::default(),
+            best_of: 1,
+            alternatives: 1,
+            minimize_overlap: false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl DrawSettings {
+    /// The default settings for lineups of `number_of_characters`, without any tag bounds
+    /// so that small lineups can pass.
+    pub(crate) fn unbounded(number_of_characters: usize) -> Self {
+        Self {
+            number_of_characters,
+            tag_bounds: BTreeMap::new(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A named snapshot of [`DrawSettings`] that can be applied in one click.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub settings: DrawSettings,
+}
+
+/// Stores `settings` under `name`, replacing any preset that already uses that name.
+pub fn save_preset(presets: &mut Vec<Preset>, name: &str, settings: &DrawSettings) {
+    match presets.iter_mut().find(|preset| preset.name == name) {
+        Some(preset) => preset.settings = settings.clone(),
+        None => presets.push(Preset {
+            name: name.to_string(),
+            settings: settings.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goal_rules_keep_the_maximum() {
+        let bound = TagBound {
+            min: 2,
+            max: Some(4),
+            or_none: true,
+        };
+        assert!(bound.with_goal_rule(bound.goal_rule(), bound.min) == bound);
+
+        let required = bound.with_goal_rule(GoalRule::Required, 3);
+        assert_eq!(
+            (required.min, required.max, required.or_none),
+            (3, Some(4), false)
+        );
+        let any = bound.with_goal_rule(GoalRule::Any, 3);
+        assert_eq!((any.min, any.max, any.or_none), (0, Some(4), false));
+    }
+}
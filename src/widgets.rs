@@ -0,0 +1,608 @@
+//! Widgets for embedding the randomizer in other egui apps. The app itself is built from them.
+
+use crate::character::{AggressionLevel, Character, Difficulty, RelationshipKind};
+use crate::constraints::{average_aggression, check_lineup, goal_ambiguity};
+use crate::roster::{Roster, TagKind};
+use crate::scoring::score;
+use crate::settings::{
+    AggressionRange, DifficultyQuota, DrawSettings, GoalRule, TagBound, MAX_ALTERNATIVES,
+    MAX_BEST_OF,
+};
+use std::collections::BTreeMap;
+
+type TierChances<'a> = Box<dyn FnMut(&DrawSettings) -> BTreeMap<Difficulty, f32> + 'a>;
+
+/// Edits every draw setting: lineup size, difficulty, aggression, trait and goal rules, and
+/// the balance score. The response is marked changed whenever the settings change.
+pub struct DrawSettingsEditor<'a> {
+    id: egui::Id,
+    settings: &'a mut DrawSettings,
+    roster: &'a Roster,
+    tier_chances: Option<TierChances<'a>>,
+}
+
+impl<'a> DrawSettingsEditor<'a> {
+    /// `id_source` tells apart several editors in the same window, like
+    /// [`egui::Grid::new`]'s.
+    pub fn new(
+        id_source: impl std::hash::Hash,
+        settings: &'a mut DrawSettings,
+        roster: &'a Roster,
+    ) -> Self {
+        Self {
+            id: egui::Id::new(id_source),
+            settings,
+            roster,
+            tier_chances: None,
+        }
+    }
+
+    /// Shows the chance of drawing each character of a difficulty next to its weight. The
+    /// closure is only called while the weights are shown, so it may be slow or cached.
+    pub fn tier_chances(
+        mut self,
+        chances: impl FnMut(&DrawSettings) -> BTreeMap<Difficulty, f32> + 'a,
+    ) -> Self {
+        self.tier_chances = Some(Box::new(chances));
+        self
+    }
+}
+
+impl egui::Widget for DrawSettingsEditor<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let Self {
+            id,
+            settings,
+            roster,
+            mut tier_chances,
+        } = self;
+        let before = settings.clone();
+
+        let mut response = ui
+            .push_id(id, |ui| {
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Number of Characters");
+                        egui::ComboBox::from_id_source(1)
+                            .selected_text(format!("{:?}", settings.number_of_characters))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    12,
+                                    "12 (2p, 3p, 4p)",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    14,
+                                    "14 (5p)",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    15,
+                                    "15 (6p)",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    17,
+                                    "17 (7p)",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    19,
+                                    "19 (8p)",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.number_of_characters,
+                                    20,
+                                    "20 (9p)",
+                                );
+                            });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Preferred Maximum Difficulty");
+                        egui::ComboBox::from_id_source(2)
+                            .selected_text(match settings.preferred_maximum_difficulty {
+                                Difficulty::Easy => "Easy",
+                                Difficulty::Medium => "Medium",
+                                Difficulty::Hard => "Hard",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut settings.preferred_maximum_difficulty,
+                                    Difficulty::Easy,
+                                    "Easy",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.preferred_maximum_difficulty,
+                                    Difficulty::Medium,
+                                    "Medium",
+                                );
+                                ui.selectable_value(
+                                    &mut settings.preferred_maximum_difficulty,
+                                    Difficulty::Hard,
+                                    "Hard",
+                                );
+                            });
+                        ui.checkbox(&mut settings.strict_maximum_difficulty, "Strict")
+                            .on_hover_text(
+                                "Never draw harder characters, even if the lineup can't be filled",
+                            );
+                    });
+
+                    ui.collapsing("Difficulty Weights", |ui| {
+                        let odds = tier_chances.as_mut().map(|chances| chances(settings));
+
+                        egui::Grid::new("difficulty_weights").show(ui, |ui| {
+                            ui.label("");
+                            ui.label("Weight");
+                            if odds.is_some() {
+                                ui.label("Chance Each");
+                            }
+                            ui.end_row();
+
+                            for difficulty in Difficulty::ALL {
+                                let mut weight = settings.difficulty_weight(difficulty);
+
+                                ui.label(difficulty.name());
+                                ui.add(
+                                    egui::DragValue::new(&mut weight)
+                                        .speed(0.05)
+                                        .clamp_range(0.0..=10.0),
+                                );
+                                if let Some(odds) = &odds {
+                                    ui.label(match odds.get(&difficulty) {
+                                        Some(odds) => format!("{:.0}%", odds * 100.0),
+                                        None => "–".to_string(),
+                                    });
+                                }
+                                ui.end_row();
+
+                                if weight != settings.difficulty_weight(difficulty) {
+                                    let mut weights: BTreeMap<Difficulty, f32> = Difficulty::ALL
+                                        .into_iter()
+                                        .map(|tier| (tier, settings.difficulty_weight(tier)))
+                                        .collect();
+                                    weights.insert(difficulty, weight);
+                                    settings.difficulty_weights = Some(weights);
+                                }
+                            }
+                        });
+
+                        if settings.difficulty_weights.is_some()
+                            && ui
+                                .button(format!(
+                                    "Reset to {} Defaults",
+                                    settings.preferred_maximum_difficulty.name()
+                                ))
+                                .clicked()
+                        {
+                            settings.difficulty_weights = None;
+                        }
+                        if odds.is_some() {
+                            ui.label(
+                                "Chances are before trait, goal and aggression rules are applied.",
+                            );
+                        }
+                    });
+
+                    ui.collapsing("Difficulty Quotas", |ui| {
+                        egui::Grid::new("difficulty_quotas").show(ui, |ui| {
+                            ui.label("");
+                            ui.label("Min");
+                            ui.label("Max");
+                            ui.label("%");
+                            ui.end_row();
+
+                            for difficulty in Difficulty::ALL {
+                                let mut quota = settings.difficulty_quota(difficulty);
+                                let limit = if quota.percent {
+                                    100
+                                } else {
+                                    settings.number_of_characters
+                                };
+                                let mut has_max = quota.max.is_some();
+                                let mut max = quota.max.unwrap_or(limit);
+
+                                ui.label(difficulty.name());
+                                ui.add(egui::DragValue::new(&mut quota.min).clamp_range(0..=limit));
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::Checkbox::without_text(&mut has_max));
+                                    ui.add_enabled(
+                                        has_max,
+                                        egui::DragValue::new(&mut max).clamp_range(0..=limit),
+                                    );
+                                });
+                                ui.add(egui::Checkbox::without_text(&mut quota.percent));
+                                ui.end_row();
+
+                                quota.max = has_max.then_some(max);
+                                if quota == DifficultyQuota::default() {
+                                    settings.difficulty_quotas.remove(&difficulty);
+                                } else if settings.difficulty_quota(difficulty) != quota {
+                                    settings.difficulty_quotas.insert(difficulty, quota);
+                                }
+                            }
+                        });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Aggression");
+                        for (level, name) in [
+                            (AggressionLevel::Peaceful, "Peaceful"),
+                            (AggressionLevel::Random, "Random"),
+                            (AggressionLevel::Aggressive, "Aggressive"),
+                        ] {
+                            let range = AggressionRange::from(level);
+                            if ui
+                                .selectable_label(settings.aggression_range == range, name)
+                                .clicked()
+                            {
+                                settings.aggression_range = range;
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let range = &mut settings.aggression_range;
+                        ui.label("Average Between");
+                        ui.add(
+                            egui::DragValue::new(&mut range.min)
+                                .speed(0.01)
+                                .clamp_range(0.0..=1.0),
+                        );
+                        ui.label("and");
+                        ui.add(
+                            egui::DragValue::new(&mut range.max)
+                                .speed(0.01)
+                                .clamp_range(0.0..=1.0),
+                        );
+                        if range.min > range.max {
+                            std::mem::swap(&mut range.min, &mut range.max);
+                        }
+                    });
+
+                    ui.collapsing("Trait Counts", |ui| {
+                        egui::Grid::new("tag_bounds").show(ui, |ui| {
+                            ui.label("");
+                            ui.label("Min");
+                            ui.label("Max");
+                            ui.label("Or None");
+                            ui.end_row();
+
+                            for tag in roster.tags.iter().filter(|tag| tag.kind == TagKind::Trait) {
+                                let mut bound = settings.tag_bound(&tag.id);
+                                let mut has_max = bound.max.is_some();
+                                let mut max = bound.max.unwrap_or(settings.number_of_characters);
+
+                                ui.label(format!("{} {}", tag.icon, tag.label));
+                                ui.add(
+                                    egui::DragValue::new(&mut bound.min)
+                                        .clamp_range(0..=settings.number_of_characters),
+                                );
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::Checkbox::without_text(&mut has_max));
+                                    ui.add_enabled(
+                                        has_max,
+                                        egui::DragValue::new(&mut max)
+                                            .clamp_range(0..=settings.number_of_characters),
+                                    );
+                                });
+                                ui.add(egui::Checkbox::without_text(&mut bound.or_none));
+                                ui.end_row();
+
+                                bound.max = has_max.then_some(max);
+                                if bound == TagBound::default() {
+                                    settings.tag_bounds.remove(&tag.id);
+                                } else if settings.tag_bound(&tag.id) != bound {
+                                    settings.tag_bounds.insert(tag.id.clone(), bound);
+                                }
+                            }
+                        });
+                    });
+
+                    ui.collapsing("Goal Rules", |ui| {
+                        egui::Grid::new("goal_rules").show(ui, |ui| {
+                            for goal in roster.goals() {
+                                let bound = settings.tag_bound(&goal.id);
+                                let mut rule = bound.goal_rule();
+                                // Switching away from "Any" starts out at the usual two characters.
+                                let mut min = if bound.min == 0 { 2 } else { bound.min };
+
+                                ui.label(format!("{} {}", goal.icon, goal.label));
+                                egui::ComboBox::from_id_source(format!("goal_rule_{}", goal.id))
+                                    .selected_text(match rule {
+                                        GoalRule::Any => "Any",
+                                        GoalRule::Required => "Required, at least",
+                                        GoalRule::NoneOrAtLeast => "None or at least",
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut rule, GoalRule::Any, "Any");
+                                        ui.selectable_value(
+                                            &mut rule,
+                                            GoalRule::Required,
+                                            "Required, at least",
+                                        );
+                                        ui.selectable_value(
+                                            &mut rule,
+                                            GoalRule::NoneOrAtLeast,
+                                            "None or at least",
+                                        );
+                                    });
+                                if rule != GoalRule::Any {
+                                    ui.add(
+                                        egui::DragValue::new(&mut min)
+                                            .clamp_range(1..=settings.number_of_characters),
+                                    );
+                                }
+                                ui.end_row();
+
+                                let bound = bound.with_goal_rule(rule, min);
+                                if bound == TagBound::default() {
+                                    settings.tag_bounds.remove(&goal.id);
+                                } else if settings.tag_bound(&goal.id) != bound {
+                                    settings.tag_bounds.insert(goal.id.clone(), bound);
+                                }
+                            }
+                        });
+                    });
+
+                    ui.collapsing("Balance Score", |ui| {
+                        let weights = &mut settings.score_weights;
+                        egui::Grid::new("score_weights").show(ui, |ui| {
+                            for (name, weight) in [
+                                ("Aggression", &mut weights.aggression),
+                                ("Goal Ambiguity", &mut weights.ambiguity),
+                                ("Difficulty Mix", &mut weights.difficulty_mix),
+                                ("Trait Diversity", &mut weights.trait_diversity),
+                            ] {
+                                ui.label(name);
+                                ui.add(
+                                    egui::DragValue::new(weight)
+                                        .speed(0.05)
+                                        .clamp_range(0.0..=10.0),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Pick Best Of");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.best_of)
+                                .clamp_range(1..=MAX_BEST_OF),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Alternatives");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.alternatives)
+                                .clamp_range(1..=MAX_ALTERNATIVES),
+                        );
+                        ui.add_enabled(
+                            settings.alternatives > 1,
+                            egui::Checkbox::new(&mut settings.minimize_overlap, "Minimize Overlap"),
+                        );
+                    });
+                })
+            })
+            .inner
+            .response;
+        if *settings != before {
+            response.mark_changed();
+        }
+        response
+    }
+}
+
+/// Shows a lineup with its checks against the settings, its metrics, balance score and
+/// relationships.
+pub struct LineupView<'a> {
+    id: egui::Id,
+    lineup: &'a [Character],
+    roster: &'a Roster,
+    settings: &'a DrawSettings,
+    pool: &'a [Character],
+    detailed: bool,
+}
+
+impl<'a> LineupView<'a> {
+    /// The lineup is scored against the roster's characters unless [`Self::pool`] says
+    /// otherwise. `id_source` tells apart several views in the same window.
+    pub fn new(
+        id_source: impl std::hash::Hash,
+        lineup: &'a [Character],
+        roster: &'a Roster,
+        settings: &'a DrawSettings,
+    ) -> Self {
+        Self {
+            id: egui::Id::new(id_source),
+            lineup,
+            roster,
+            settings,
+            pool: &roster.characters,
+            detailed: false,
+        }
+    }
+
+    /// The characters the lineup was drawn from.
+    pub fn pool(mut self, pool: &'a [Character]) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Shows each character's difficulty, tags and aggression instead of only their names.
+    pub fn detailed(mut self, detailed: bool) -> Self {
+        self.detailed = detailed;
+        self
+    }
+}
+
+impl egui::Widget for LineupView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let Self {
+            id,
+            lineup,
+            roster,
+            settings,
+            pool,
+            detailed,
+        } = self;
+
+        ui.push_id(id, |ui| {
+            ui.vertical(|ui| {
+                if !detailed {
+                    for character in lineup {
+                        ui.label(character.name.as_str());
+                    }
+                } else {
+                    egui::Grid::new("answer").striped(true).show(ui, |ui| {
+                        ui.label("");
+                        ui.label("Difficulty");
+                        for tag in &roster.tags {
+                            ui.label(tag.label.as_str());
+                        }
+                        ui.label("Aggression");
+                        ui.end_row();
+
+                        for character in lineup {
+                            ui.label(character.name.as_str());
+                            ui.label(match character.difficulty {
+                                Difficulty::Easy => "Easy",
+                                Difficulty::Medium => "Medium",
+                                Difficulty::Hard => "Hard",
+                            });
+                            for tag in &roster.tags {
+                                ui.label(match character.has_tag(&tag.id) {
+                                    true => tag.icon.as_str(),
+                                    false => "",
+                                });
+                            }
+                            ui.label(character.aggression.to_string());
+                            ui.end_row();
+                        }
+                    });
+                }
+
+                ui.separator();
+
+                for check in check_lineup(lineup, roster, settings) {
+                    ui.label(format!(
+                        "{} {}: {}",
+                        if check.passed { "✔" } else { "✘" },
+                        check.name,
+                        check.detail
+                    ));
+                }
+
+                let tag_counts = roster.tag_counts(lineup);
+                let goal_counts: Vec<String> = roster
+                    .goals()
+                    .map(|goal| format!("{} {}", goal.icon, tag_counts[&goal.id]))
+                    .collect();
+                ui.label(format!(
+                    "Average aggression: {:.2}",
+                    average_aggression(lineup)
+                ));
+                ui.label(match goal_ambiguity(lineup, roster) {
+                    Some(ambiguity) => format!(
+                        "Goal ambiguity: {:.0}% ({})",
+                        ambiguity * 100.0,
+                        goal_counts.join(", ")
+                    ),
+                    None => "Goal ambiguity: no goals in play".to_string(),
+                });
+
+                let balance = score(lineup, pool, roster, settings);
+                ui.separator();
+                ui.label(format!("Balance score: {:.2}", balance.total));
+                for part in &balance.parts {
+                    ui.label(format!(
+                        "    {}: {:.2} (weight {:.2})",
+                        part.name, part.score, part.weight
+                    ));
+                }
+
+                relationship_graph(ui, lineup);
+            })
+        })
+        .inner
+        .response
+    }
+}
+
+/// Draws the relationships of the drawn characters as a circle of names with arrows between
+/// them. Characters that are pointed at but weren't drawn are greyed out.
+fn relationship_graph(ui: &mut egui::Ui, lineup: &[Character]) {
+    let edges: Vec<(&str, RelationshipKind, &str)> = lineup
+        .iter()
+        .flat_map(|character| {
+            character
+                .relationships
+                .iter()
+                .map(|(kind, name)| (character.name.as_str(), kind, name.as_str()))
+        })
+        .collect();
+    if edges.is_empty() {
+        return;
+    }
+
+    let mut nodes: Vec<&str> = vec![];
+    for (from, _, to) in &edges {
+        for name in [*from, *to] {
+            if !nodes.contains(&name) {
+                nodes.push(name);
+            }
+        }
+    }
+    let color = |kind| match kind {
+        RelationshipKind::Targets => egui::Color32::RED,
+        RelationshipKind::Rivals => egui::Color32::GOLD,
+        RelationshipKind::Protects => egui::Color32::GREEN,
+    };
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Relationships:");
+        for kind in RelationshipKind::ALL {
+            ui.colored_label(color(kind), format!("→ {}", kind.name()));
+        }
+    });
+
+    let size = egui::vec2(ui.available_width().min(400.0), 300.0);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let center = response.rect.center();
+    let radius = response.rect.width().min(response.rect.height()) / 2.0 - 30.0;
+    let position = |name: &str| {
+        let index = nodes.iter().position(|node| *node == name).unwrap_or(0);
+        let angle = index as f32 / nodes.len() as f32 * std::f32::consts::TAU;
+        center + radius * egui::vec2(angle.cos(), angle.sin())
+    };
+
+    for (from, kind, to) in &edges {
+        let (start, end) = (position(from), position(to));
+        let direction = (end - start).normalized();
+        // Leave room for the names at both ends.
+        painter.arrow(
+            start + direction * 15.0,
+            end - start - direction * 30.0,
+            egui::Stroke::new(1.5, color(*kind)),
+        );
+    }
+    for name in &nodes {
+        let drawn = lineup.iter().any(|character| character.name == *name);
+        painter.text(
+            position(name),
+            egui::Align2::CENTER_CENTER,
+            name,
+            egui::FontId::proportional(12.0),
+            if drawn {
+                ui.visuals().text_color()
+            } else {
+                ui.visuals().weak_text_color()
+            },
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Runs `stationfall_randomizer serve` on a free local port and talks to it over HTTP.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+/// A running `serve` process, killed when dropped.
+struct Instance {
+    child: Child,
+    address: String,
+}
+
+impl Instance {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_stationfall_randomizer"))
+            .args(["serve", "127.0.0.1:0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("the binary starts");
+
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let address = stdout
+            .lines()
+            .map(Result::unwrap)
+            .find_map(|line| {
+                line.strip_prefix("Listening on http://")
+                    .map(ToString::to_string)
+            })
+            .expect("the server reports its address");
+        Self { child, address }
+    }
+
+    /// Sends a bare HTTP/1.0 request and returns the status code and body.
+    fn send(&self, method: &str, path: &str, body: &Value) -> (u16, Value) {
+        let body = if body.is_null() {
+            String::new()
+        } else {
+            body.to_string()
+        };
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.0\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn names(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|name| name.as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn lists_the_roster() {
+    let server = Instance::start();
+    let (status, roster) = server.send("GET", "/roster", &Value::Null);
+
+    assert_eq!(status, 200);
+    assert!(roster["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|tag| tag["id"] == "officer"));
+    assert!(roster["characters"].as_array().unwrap().len() >= 20);
+}
+
+#[test]
+fn draws_a_valid_lineup_with_default_settings() {
+    let server = Instance::start();
+    let (status, report) = server.send("POST", "/draw", &Value::Null);
+
+    assert_eq!(status, 200);
+    assert_eq!(report["lineup"].as_array().unwrap().len(), 15);
+    assert_eq!(report["valid"], true);
+    assert!(report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|check| check["passed"] == true));
+}
+
+#[test]
+fn draws_the_same_lineup_for_the_same_seed() {
+    let server = Instance::start();
+    let query = json!({ "settings": { "number_of_characters": 12 }, "seed": 42 });
+    let (_, first) = server.send("POST", "/draw", &query);
+    let (_, second) = server.send("POST", "/draw", &query);
+
+    assert_eq!(names(&first["lineup"]).len(), 12);
+    assert_eq!(first["lineup"], second["lineup"]);
+}
+
+#[test]
+fn draws_with_custom_characters() {
+    let server = Instance::start();
+    let (_, roster) = server.send("GET", "/roster", &Value::Null);
+    let total = roster["characters"].as_array().unwrap().len();
+
+    // Drawing the whole pool must include the custom character.
+    let query = json!({
+        "settings": { "number_of_characters": total + 1, "tag_bounds": {} },
+        "custom_characters": [
+            { "name": "Janitor", "difficulty": "Easy", "aggression": 0.5, "tags": ["robot"] }
+        ]
+    });
+    let (status, report) = server.send("POST", "/draw", &query);
+
+    assert_eq!(status, 200);
+    assert!(names(&report["lineup"]).contains(&"Janitor".to_string()));
+}
+
+#[test]
+fn reports_why_a_draw_fails() {
+    let server = Instance::start();
+    let query = json!({
+        "settings": {
+            "difficulty_quotas": { "Hard": { "min": 15, "max": null, "percent": false } }
+        }
+    });
+    let (status, failure) = server.send("POST", "/draw", &query);
+
+    assert_eq!(status, 422);
+    assert!(!failure["shortages"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn validates_a_drawn_lineup() {
+    let server = Instance::start();
+    let (_, drawn) = server.send("POST", "/draw", &json!({ "seed": 7 }));
+    let (status, report) = server.send("POST", "/validate", &json!({ "lineup": drawn["lineup"] }));
+
+    assert_eq!(status, 200);
+    assert_eq!(report["valid"], true);
+    assert_eq!(report["average_aggression"], drawn["average_aggression"]);
+}
+
+#[test]
+fn flags_lineups_that_break_the_rules() {
+    let server = Instance::start();
+    let (_, roster) = server.send("GET", "/roster", &Value::Null);
+    let without_robots: Vec<&Value> = roster["characters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|character| {
+            !character["tags"]
+                .as_array()
+                .unwrap()
+                .contains(&json!("robot"))
+        })
+        .map(|character| &character["name"])
+        .take(15)
+        .collect();
+
+    let (status, report) = server.send("POST", "/validate", &json!({ "lineup": without_robots }));
+
+    assert_eq!(status, 200);
+    assert_eq!(report["valid"], false);
+    assert!(report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(
+            |check| check["name"].as_str().unwrap().ends_with("Robot") && check["passed"] == false
+        ));
+}
+
+#[test]
+fn rejects_unknown_characters() {
+    let server = Instance::start();
+    let (status, body) = server.send("POST", "/validate", &json!({ "lineup": ["Nobody"] }));
+
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("Nobody"));
+}
+
+#[test]
+fn rejects_characters_listed_twice() {
+    let server = Instance::start();
+    let lineup = json!({ "lineup": ["Cyborg", "Astrochimp", "Cyborg"] });
+    let (status, body) = server.send("POST", "/validate", &lineup);
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"], "Characters listed more than once: Cyborg");
+}
+
+#[test]
+fn counts_feasible_lineups() {
+    let server = Instance::start();
+    let (status, feasibility) = server.send("POST", "/feasibility", &Value::Null);
+
+    assert_eq!(status, 200);
+    assert!(feasibility["lineups"].as_u64().unwrap() > 0);
+
+    let impossible = json!({
+        "settings": { "tag_bounds": { "officer": { "min": 50, "max": null, "or_none": false } } }
+    });
+    let (status, feasibility) = server.send("POST", "/feasibility", &impossible);
+
+    assert_eq!(status, 200);
+    assert_eq!(feasibility["lineups"], 0);
+    assert!(names(&feasibility["blocking"])
+        .iter()
+        .any(|rule| rule.ends_with("Officer")));
+}
+
+#[test]
+fn rejects_malformed_requests() {
+    let server = Instance::start();
+
+    assert_eq!(
+        server.send("POST", "/draw", &json!({ "settings": 3 })).0,
+        400
+    );
+    assert_eq!(
+        server
+            .send("POST", "/draw", &json!({ "settings": { "best_of": 0 } }))
+            .0,
+        400
+    );
+    assert_eq!(
+        server
+            .send(
+                "POST",
+                "/draw",
+                &json!({ "settings": { "best_of": 100_000 } })
+            )
+            .0,
+        400
+    );
+    assert_eq!(
+        server
+            .send(
+                "POST",
+                "/draw",
+                &json!({ "settings": { "alternatives": 6 } })
+            )
+            .0,
+        400
+    );
+    assert_eq!(server.send("GET", "/draw", &Value::Null).0, 405);
+    assert_eq!(server.send("GET", "/nowhere", &Value::Null).0, 404);
+}
+
+#[test]
+fn refuses_oversized_bodies() {
+    let server = Instance::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    write!(
+        stream,
+        "POST /draw HTTP/1.0\r\nContent-Length: 100000000\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.0 413"));
+}
+
+#[test]
+fn rejects_quotas_and_sizes_out_of_range() {
+    let server = Instance::start();
+    let huge = json!({ "settings": { "number_of_characters": 100_000 } });
+    let (status, body) = server.send("POST", "/draw", &huge);
+    assert_eq!(status, 400, "{body}");
+
+    let quota = json!({
+        "settings": {
+            "difficulty_quotas": { "Hard": { "min": 0, "max": u64::MAX, "percent": true } }
+        }
+    });
+    for path in ["/draw", "/feasibility"] {
+        let (status, body) = server.send("POST", path, &quota);
+        assert_eq!(status, 400, "{body}");
+    }
+
+    // The server is still up.
+    assert_eq!(server.send("GET", "/roster", &Value::Null).0, 200);
+}
+
+#[test]
+fn rejects_invalid_custom_characters() {
+    let server = Instance::start();
+    let janitor = json!({ "name": "J", "difficulty": "Easy", "aggression": 0.5, "tags": [] });
+    let cases = [
+        (json!([janitor, janitor]), "J is listed more than once"),
+        (
+            json!([{ "name": "J", "difficulty": "Easy", "aggression": 7.0, "tags": [] }]),
+            "J has aggression 7",
+        ),
+        (
+            json!([{ "name": "J", "difficulty": "Easy", "aggression": 0.5, "tags": ["nonsense"] }]),
+            "J has the unknown tag \"nonsense\"",
+        ),
+        (
+            json!([{ "name": " ", "difficulty": "Easy", "aggression": 0.5, "tags": [] }]),
+            "a custom character has no name",
+        ),
+    ];
+    for (custom_characters, error) in cases {
+        let query = json!({ "custom_characters": custom_characters });
+        let (status, body) = server.send("POST", "/draw", &query);
+
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains(error), "{body}");
+    }
+}
@@ -0,0 +1,69 @@
+//! Runs `stationfall_randomizer validate` on typed-out lineups.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `validate` with `args`, feeding it `stdin`, and returns the exit code and output.
+fn validate(args: &[&str], stdin: &str) -> (i32, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_stationfall_randomizer"))
+        .arg("validate")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the binary starts");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn passes_a_pasted_lineup_that_meets_the_rules() {
+    let pasted = "1. Astrochimp\n2. Counselor\n3. Cyborg\n4. Daredevil\n5. Engineer\n6. Exile\n\
+                  7. Inspector\n8. Maintenance Clones\n9. Medical\n10. Security\n11. station chief\n\
+                  12. Stowaway\n13. Troubleshooter\n14. Microbiologist\n15. Colonol\n";
+    let (code, output) = validate(&[], pasted);
+
+    assert_eq!(code, 0, "{output}");
+    assert!(output.contains("Read \"Colonol\" as Colonel."));
+    assert!(output.contains("Average aggression:"));
+}
+
+#[test]
+fn reports_broken_rules_and_unknown_names() {
+    let (code, output) = validate(&["Astrochimp, chimp", "Colonel"], "");
+    assert_eq!(code, 1);
+    assert!(output.contains("Astrochimp is listed more than once."));
+    assert!(output.contains("The settings call for 15 characters, not 2."));
+
+    let (code, output) = validate(&["Zookeeper"], "");
+    assert_eq!(code, 2);
+    assert!(output.contains("\"Zookeeper\" matches no character."));
+}
+
+#[test]
+fn reads_settings_from_a_file() {
+    let path = std::env::temp_dir().join("stationfall_validate_settings.json");
+    std::fs::write(&path, r#"{ "number_of_characters": 2, "tag_bounds": {} }"#).unwrap();
+    let (code, output) = validate(
+        &[
+            "--settings",
+            path.to_str().unwrap(),
+            "Astrochimp",
+            "Colonel",
+        ],
+        "",
+    );
+
+    assert_eq!(code, 0, "{output}");
+}